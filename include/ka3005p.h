@@ -0,0 +1,141 @@
+/* C interface of the ka3005p library, generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#ifndef KA3005P_H
+#define KA3005P_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The power supply, opened by ka3005p_open and released by ka3005p_close
+typedef struct Ka3005p Ka3005p;
+
+// Result of a call, negative values are errors
+typedef enum Ka3005pResult {
+  // The call succeeded
+  KA3005P_RESULT_OK = 0,
+  // Any error without a more specific code, e.g. an I/O error
+  KA3005P_RESULT_ERROR = -1,
+  // No (compatible) power supply was found
+  KA3005P_RESULT_DEVICE_NOT_FOUND = -2,
+  // The power supply didn't respond
+  KA3005P_RESULT_TIMEOUT = -3,
+  // The power supply responded with data which couldn't be decoded
+  KA3005P_RESULT_PROTOCOL = -4,
+  // A value is outside of the range supported by the power supply
+  KA3005P_RESULT_LIMIT_EXCEEDED = -5,
+  // A pointer was NULL or a string wasn't valid UTF-8
+  KA3005P_RESULT_INVALID_ARGUMENT = -6,
+  // The buffer is too small for the result, the required size was stored
+  KA3005P_RESULT_BUFFER_TOO_SMALL = -7,
+} Ka3005pResult;
+
+// CC or CV mode
+typedef enum Ka3005pMode {
+  // Constant current
+  KA3005P_MODE_CC = 0,
+  // Constant voltage
+  KA3005P_MODE_CV = 1,
+} Ka3005pMode;
+
+// Measurements, settings and flags of the power supply
+typedef struct Ka3005pStatus {
+  // Measured voltage in volts
+  float voltage;
+  // Measured current in amps
+  float current;
+  // Target voltage in volts
+  float set_voltage;
+  // Current limit in amps
+  float set_current;
+  // Output enabled
+  bool output;
+  // Mode of channel 1
+  enum Ka3005pMode mode;
+  // Interface beep enabled
+  bool beep;
+  // Physical buttons locked
+  bool locked;
+} Ka3005pStatus;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message of the last failed call on this thread, or NULL if no call failed yet.
+//
+// The string stays valid until the next failing call on the same thread.
+const char *ka3005p_last_error(void);
+
+// Store the names of the serial ports with a power supply in `buffer`, separated by newlines.
+//
+// `size` has to contain the size of `buffer` and receives the number of bytes used (including
+// the terminating NUL). If `buffer` is NULL or too small, the required size is stored and
+// `KA3005P_RESULT_BUFFER_TOO_SMALL` is returned.
+//
+// # Safety
+//
+// `buffer` must be NULL or valid for `*size` bytes, `size` must be a valid pointer.
+enum Ka3005pResult ka3005p_list_ports(char *buffer, size_t *size);
+
+// Open the power supply at `port_name`, or the first one found if `port_name` is NULL.
+//
+// On success the handle is stored in `device`, it has to be released with [`ka3005p_close`].
+//
+// # Safety
+//
+// `port_name` must be NULL or a NUL terminated string, `device` must be a valid pointer.
+enum Ka3005pResult ka3005p_open(const char *port_name, Ka3005p **device);
+
+// Close the connection to the power supply, NULL is ignored.
+//
+// # Safety
+//
+// `device` must be NULL or a handle returned by [`ka3005p_open`] which wasn't closed yet.
+void ka3005p_close(Ka3005p *device);
+
+// Store the identification string of the power supply in `buffer`, see [`ka3005p_list_ports`] for `size`.
+//
+// # Safety
+//
+// `device` must be a handle returned by [`ka3005p_open`], `buffer` must be NULL or valid for
+// `*size` bytes and `size` must be a valid pointer.
+enum Ka3005pResult ka3005p_identify(Ka3005p *device,
+                                    char *buffer,
+                                    size_t *size);
+
+// Set the target voltage in volts.
+//
+// # Safety
+//
+// `device` must be a handle returned by [`ka3005p_open`].
+enum Ka3005pResult ka3005p_set_voltage(Ka3005p *device, float voltage);
+
+// Set the current limit in amps.
+//
+// # Safety
+//
+// `device` must be a handle returned by [`ka3005p_open`].
+enum Ka3005pResult ka3005p_set_current(Ka3005p *device, float current);
+
+// Switch the output on or off.
+//
+// # Safety
+//
+// `device` must be a handle returned by [`ka3005p_open`].
+enum Ka3005pResult ka3005p_set_output(Ka3005p *device, bool enable);
+
+// Read the measurements, settings and flags of the power supply into `status`.
+//
+// # Safety
+//
+// `device` must be a handle returned by [`ka3005p_open`], `status` must be a valid pointer.
+enum Ka3005pResult ka3005p_read_status(Ka3005p *device, struct Ka3005pStatus *status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KA3005P_H */
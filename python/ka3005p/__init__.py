@@ -1 +1,15 @@
-from ka3005p import PowerSupply
+from ka3005p import (
+    AsyncPowerSupply,
+    Channel,
+    DeviceNotFound,
+    Error,
+    Flags,
+    LimitExceeded,
+    Lock,
+    Mode,
+    PowerSupply,
+    ProtocolError,
+    Sample,
+    Status,
+    Timeout,
+)
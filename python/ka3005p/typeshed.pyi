@@ -1,15 +1,116 @@
 # TODO: Fix outdated API bits
-from typing import List, Optional, Any, Union
+from types import TracebackType
+from typing import (
+    TYPE_CHECKING,
+    Any,
+    Awaitable,
+    Callable,
+    ClassVar,
+    Dict,
+    List,
+    Literal,
+    Optional,
+    Type,
+    overload,
+)
+
+if TYPE_CHECKING:
+    # Only needed for acquire(dataframe=True)
+    import pandas
+
+class Error(Exception): ...
+
+class DeviceNotFound(Error): ...
+
+class Timeout(Error): ...
+
+class ProtocolError(Error): ...
+
+class LimitExceeded(Error): ...
+
+class Mode:
+    CC: ClassVar["Mode"]
+    CV: ClassVar["Mode"]
+
+    def __int__(self) -> int: ...
+
+    def __eq__(self, other: object) -> bool: ...
+
+class Lock:
+    LOCKED: ClassVar["Lock"]
+    UNLOCKED: ClassVar["Lock"]
+
+    def __int__(self) -> int: ...
+
+    def __eq__(self, other: object) -> bool: ...
+
+class Channel:
+    ONE: ClassVar["Channel"]
+    TWO: ClassVar["Channel"]
+
+    def __int__(self) -> int: ...
+
+    def __eq__(self, other: object) -> bool: ...
+
+class Flags:
+    channel1: Mode
+    channel2: Mode
+    beep: bool
+    lock: Lock
+    output: bool
+    raw: int
+
+    def mode(self, channel: Channel) -> Mode: ...
+
+class Status:
+    voltage: float
+    current: float
+    set_voltage: float
+    set_current: float
+    flags: Flags
+
+class Sample:
+    time: float
+    voltage: float
+    current: float
+    mode: Mode
 
 class PowerSupply:
 
-    def __init__(self, serial_port: Optional[str] = None) -> None: ...
+    def __init__(
+        self,
+        serial_port: Optional[str] = None,
+        *,
+        baud_rate: int = 9600,
+        timeout: float = 0.06,
+        command_delay: float = 0.0,
+        terminator: str = "",
+        retries: int = 0,
+        reconnect: bool = False,
+        model: Optional[str] = None,
+        slew_voltage: Optional[float] = None,
+        slew_current: Optional[float] = None,
+        voltage: Optional[float] = None,
+        current: Optional[float] = None,
+        restore: bool = False,
+    ) -> None: ...
+
+    def __enter__(self) -> "PowerSupply": ...
+
+    def __exit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_value: Optional[BaseException],
+        traceback: Optional[TracebackType],
+    ) -> bool: ...
 
     @staticmethod
     def list_power_supplies() -> List[str]: ...
 
     def execute(self, command: str) -> List[int]: ...
 
+    def identify(self) -> str: ...
+
     @property
     def current(self) -> float: ...
 
@@ -23,19 +124,27 @@ class PowerSupply:
     def voltage(self, v: float) -> None: ...
 
     @property
-    def status(self) -> str: ...
+    def set_current(self) -> float: ...
 
     @property
-    def on(self) -> bool: ...
+    def set_voltage(self) -> float: ...
 
-    @on.setter
-    def on(self, enable: bool) -> None: ...
+    @property
+    def status(self) -> Status: ...
 
     @property
-    def off(self) -> bool: ...
+    def flags(self) -> Flags: ...
+
+    def enable(self) -> None: ...
+
+    def disable(self) -> None: ...
 
-    @off.setter
-    def off(self, disable: bool) -> None: ...
+    def is_on(self) -> bool: ...
+
+    def is_off(self) -> bool: ...
+
+    @property
+    def beep(self) -> bool: ...
 
     @beep.setter
     def beep(self, enable: bool) -> None: ...
@@ -49,3 +158,90 @@ class PowerSupply:
 
     @ovp.setter
     def ovp(self, enable: bool) -> None: ...
+
+    @overload
+    def acquire(
+        self,
+        duration: float,
+        interval: float,
+        callback: Optional[Callable[[Sample], Optional[bool]]] = None,
+        *,
+        dataframe: Literal[False] = False,
+    ) -> Dict[str, Any]: ...
+
+    @overload
+    def acquire(
+        self,
+        duration: float,
+        interval: float,
+        callback: Optional[Callable[[Sample], Optional[bool]]] = None,
+        *,
+        dataframe: Literal[True],
+    ) -> "pandas.DataFrame": ...
+
+class AsyncPowerSupply:
+
+    def __init__(
+        self,
+        serial_port: Optional[str] = None,
+        *,
+        baud_rate: int = 9600,
+        timeout: float = 0.06,
+        command_delay: float = 0.0,
+        terminator: str = "",
+        retries: int = 0,
+        reconnect: bool = False,
+        model: Optional[str] = None,
+        slew_voltage: Optional[float] = None,
+        slew_current: Optional[float] = None,
+        voltage: Optional[float] = None,
+        current: Optional[float] = None,
+        restore: bool = False,
+    ) -> None: ...
+
+    def __aenter__(self) -> Awaitable["AsyncPowerSupply"]: ...
+
+    def __aexit__(
+        self,
+        exc_type: Optional[Type[BaseException]],
+        exc_value: Optional[BaseException],
+        traceback: Optional[TracebackType],
+    ) -> Awaitable[bool]: ...
+
+    def execute(self, command: str) -> Awaitable[List[int]]: ...
+
+    def identify(self) -> Awaitable[str]: ...
+
+    def get_current(self) -> Awaitable[float]: ...
+
+    def get_voltage(self) -> Awaitable[float]: ...
+
+    def get_set_current(self) -> Awaitable[float]: ...
+
+    def get_set_voltage(self) -> Awaitable[float]: ...
+
+    def set_current(self, i: float) -> Awaitable[None]: ...
+
+    def set_voltage(self, v: float) -> Awaitable[None]: ...
+
+    def get_status(self) -> Awaitable[Status]: ...
+
+    def get_flags(self) -> Awaitable[Flags]: ...
+
+    def enable(self) -> Awaitable[None]: ...
+
+    def disable(self) -> Awaitable[None]: ...
+
+    def is_on(self) -> Awaitable[bool]: ...
+
+    def is_off(self) -> Awaitable[bool]: ...
+
+    def set_beep(self, enable: bool) -> Awaitable[None]: ...
+
+    def save(self, id: int) -> Awaitable[None]: ...
+
+    def load(self, id: int) -> Awaitable[None]: ...
+
+    def set_ocp(self, enable: bool) -> Awaitable[None]: ...
+
+    def set_ovp(self, enable: bool) -> Awaitable[None]: ...
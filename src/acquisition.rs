@@ -0,0 +1,150 @@
+//! Sample the measured voltage and current at a fixed interval.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! for sample in dev.acquire(Duration::from_secs(10), Duration::from_millis(200)) {
+//!     let sample = sample.unwrap();
+//!     println!("{:?} {} V {} A", sample.time, sample.voltage, sample.current);
+//! }
+//! ```
+use crate::{Ka3005p, Mode};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A single measurement
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// Time since the acquisition started
+    pub time: Duration,
+    /// Measured voltage in volts
+    pub voltage: f32,
+    /// Measured current in amps
+    pub current: f32,
+    /// Mode of channel 1
+    pub mode: Mode,
+}
+
+/// Timing of an acquisition, for callers which can't keep the power supply borrowed between samples.
+pub(crate) struct Schedule {
+    duration: Duration,
+    interval: Duration,
+    start: Instant,
+    next: Duration,
+}
+
+impl Schedule {
+    pub(crate) fn new(duration: Duration, interval: Duration) -> Self {
+        Schedule {
+            duration,
+            interval,
+            start: Instant::now(),
+            next: Duration::ZERO,
+        }
+    }
+
+    /// Wait for the next slot, returns the time since the start or `None` once the duration passed.
+    pub(crate) fn wait(&mut self) -> Option<Duration> {
+        if self.next >= self.duration {
+            return None;
+        }
+        thread::sleep(self.next.saturating_sub(self.start.elapsed()));
+        Some(self.start.elapsed())
+    }
+
+    /// Schedule the next slot after a sample was taken, a `failed` sample stops the acquisition.
+    pub(crate) fn done(&mut self, failed: bool) {
+        // Skip the slots which already passed, so the samples stay aligned to the interval
+        let elapsed = self.start.elapsed();
+        self.next = match self.interval.as_nanos() {
+            0 => elapsed,
+            interval => {
+                let slots = elapsed.as_nanos() / interval + 1;
+                self.interval
+                    .saturating_mul(u32::try_from(slots).unwrap_or(u32::MAX))
+            }
+        };
+        if failed {
+            // Stop after an error, otherwise the caller may loop for the full duration
+            self.next = self.duration;
+        }
+    }
+}
+
+/// Iterator over the samples of an acquisition, see [`Ka3005p::acquire`].
+pub struct Samples<'a> {
+    dev: &'a mut Ka3005p,
+    schedule: Schedule,
+}
+
+impl Iterator for Samples<'_> {
+    type Item = anyhow::Result<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let time = self.schedule.wait()?;
+        let sample = self.dev.sample(time);
+        self.schedule.done(sample.is_err());
+        Some(sample)
+    }
+}
+
+impl Ka3005p {
+    /// Sample the measured voltage, current and mode every `interval` for `duration`.
+    ///
+    /// The samples are taken at multiples of `interval` after the start. If the power supply
+    /// is too slow for the interval, samples are skipped. The iteration stops after the first error.
+    pub fn acquire(&mut self, duration: Duration, interval: Duration) -> Samples<'_> {
+        Samples {
+            dev: self,
+            schedule: Schedule::new(duration, interval),
+        }
+    }
+
+    pub(crate) fn sample(&mut self, time: Duration) -> anyhow::Result<Sample> {
+        Ok(Sample {
+            time,
+            voltage: self.read_measured_voltage()?,
+            current: self.read_measured_current()?,
+            mode: self.read_flags()?.channel1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_acquire() {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("5.00"))
+            .on("IOUT1?", Reply::data("0.100"))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let interval = Duration::from_millis(20);
+        let samples: Vec<Sample> = dev
+            .acquire(Duration::from_millis(100), interval)
+            .collect::<anyhow::Result<_>>()
+            .unwrap();
+        assert!(!samples.is_empty() && samples.len() <= 5);
+        for (slot, sample) in (0..).zip(&samples) {
+            assert!(sample.time >= interval * slot);
+        }
+        assert_eq!(samples[0].voltage, 5.0);
+        assert_eq!(samples[0].current, 0.1);
+        assert_eq!(samples[0].mode, Mode::Cv);
+    }
+
+    #[test]
+    fn test_acquire_stops_on_error() {
+        let mut dev = Ka3005p::new_from_serial(Box::new(MockPort::new())).unwrap();
+        let samples: Vec<_> = dev
+            .acquire(Duration::from_secs(10), Duration::from_millis(1))
+            .collect();
+        assert_eq!(samples.len(), 1);
+        assert!(samples[0].is_err());
+    }
+}
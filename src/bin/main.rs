@@ -1,15 +1,93 @@
 #![deny(warnings)]
 use anyhow::Context;
 use clap::Parser;
+use ka3005p::check::Report;
+use ka3005p::cli::{CheckFile, PresetFile, ReportFile};
+use ka3005p::proxy::Proxy;
+use ka3005p::transcript::{Recorder, ReplayPort};
+use ka3005p::watch::{Quantity, Watcher};
+use ka3005p::watchdog::Watchdog;
 use std::convert::TryInto;
-use std::io::BufRead;
-use std::process::exit;
+use std::io::{BufRead, Write};
+use std::ops::DerefMut;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-fn main() -> ::anyhow::Result<(), anyhow::Error> {
+mod tui;
+
+/// The power supply, optionally guarded by a watchdog.
+enum Supply {
+    Direct(Box<ka3005p::Ka3005p>),
+    Guarded(Watchdog),
+}
+
+impl Supply {
+    /// Access the power supply, this also renews the heartbeat of the watchdog.
+    fn lock(&mut self) -> Box<dyn DerefMut<Target = ka3005p::Ka3005p> + '_> {
+        match self {
+            Supply::Direct(dev) => Box::new(dev.as_mut()),
+            Supply::Guarded(watchdog) => {
+                watchdog.feed();
+                Box::new(watchdog.lock())
+            }
+        }
+    }
+
+    /// Renew the heartbeat of the watchdog without holding the power supply.
+    /// Returns how long to wait at most until it has to be renewed again.
+    fn feed(&self) -> Duration {
+        match self {
+            Supply::Direct(_) => Duration::MAX,
+            Supply::Guarded(watchdog) => {
+                watchdog.feed();
+                watchdog.timeout() / 2
+            }
+        }
+    }
+
+    /// Wait without holding the power supply, the heartbeat of the watchdog is renewed meanwhile.
+    fn sleep(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        loop {
+            let heartbeat = self.feed();
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return;
+            }
+            std::thread::sleep(remaining.min(heartbeat));
+        }
+    }
+}
+
+fn main() -> ExitCode {
     human_panic::setup_panic!();
     env_logger::init();
-    let args = ka3005p::cli::Ka3005p::parse();
+    match run(ka3005p::cli::Ka3005p::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            exit_code(&e)
+        }
+    }
+}
+
+/// Exit code for an error, see the help of the `get` command. Clap exits with 2 on invalid arguments.
+fn exit_code(error: &anyhow::Error) -> ExitCode {
+    ExitCode::from(match ka3005p::Error::classify(error) {
+        Some(ka3005p::Error::LimitExceeded(_)) => 2,
+        Some(ka3005p::Error::DeviceNotFound(_)) => 3,
+        Some(ka3005p::Error::Timeout) => 4,
+        Some(ka3005p::Error::Protocol(_)) => 5,
+        _ => 1,
+    })
+}
 
+/// Exit code of `wait-for` if the conditions didn't hold in time
+const WAIT_TIMEOUT: u8 = 6;
+/// Exit code of `check` if a measurement failed, or of `cycle` if a cycle failed
+const CHECK_FAILED: u8 = 7;
+
+fn run(args: ka3005p::cli::Ka3005p) -> anyhow::Result<ExitCode> {
     if let ka3005p::cli::Command::List { verbose } = args.command {
         let devices = if verbose {
             // Verbose. List everything
@@ -20,28 +98,269 @@ fn main() -> ::anyhow::Result<(), anyhow::Error> {
         };
 
         println!("{:#?}", devices);
-        exit(0);
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let mut serial = if let Some(device) = args.device {
-        // User specified a device. Use that
-        ka3005p::Ka3005p::new(&device)?
-    } else {
-        // Otherwise find the device automatically
-        ka3005p::find_serial_port()?
+    if let ka3005p::cli::Command::Proxy { host, .. } = &args.command {
+        let device = args.builder().open_port()?;
+        let mut proxy = match host {
+            Some(host) => Proxy::new(args.builder().port_name(host).open_port()?, device),
+            #[cfg(unix)]
+            None => Proxy::with_pty(device)?,
+            #[cfg(not(unix))]
+            None => anyhow::bail!("Pseudo terminals are not supported on this platform"),
+        };
+        println!(
+            "Forwarding {}",
+            proxy.host_name().unwrap_or_else(|| "host".to_string())
+        );
+        proxy.run(|exchange| {
+            println!(
+                "{} {}",
+                humantime::format_rfc3339_millis(std::time::SystemTime::now()),
+                exchange
+            )
+        })?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Uses the specified device or otherwise finds the device automatically
+    let mut serial = match &args.replay {
+        Some(file) => args
+            .builder()
+            .open_serial(Box::new(ReplayPort::open(file)?)),
+        None => args.builder().open()?,
+    };
+    if let Some(file) = &args.record {
+        serial.set_recorder(Some(Recorder::create(file)?));
+    }
+    // Note: The watchdog switches the output off when it is dropped, so we must not
+    // leave via `std::process::exit` from here on.
+    let mut supply = match args.watchdog {
+        Some(timeout) => Supply::Guarded(Watchdog::new(serial, timeout)),
+        None => Supply::Direct(Box::new(serial)),
     };
 
     match args.command {
         ka3005p::cli::Command::Status => {
-            println!("{}", serial.status()?);
+            println!("{}", supply.lock().status()?);
+        }
+        ka3005p::cli::Command::Apply {
+            voltage,
+            current,
+            ocp,
+            ovp,
+            beep,
+            output,
+        } => {
+            let settings = ka3005p::settings::Settings {
+                voltage,
+                current,
+                ocp,
+                ovp,
+                beep,
+                output,
+            };
+            println!("{}", supply.lock().apply(&settings)?);
+        }
+        ka3005p::cli::Command::WaitFor {
+            conditions,
+            timeout,
+            interval,
+        } => {
+            let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+            // Check once per interval with a fresh lock, so the watchdog is fed while waiting
+            loop {
+                if let Some(status) =
+                    supply
+                        .lock()
+                        .wait_for(&conditions, Duration::ZERO, interval)?
+                {
+                    println!("{}", status);
+                    break;
+                }
+                let remaining = match deadline {
+                    Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                    None => Duration::MAX,
+                };
+                if remaining.is_zero() {
+                    let conditions: Vec<String> =
+                        conditions.iter().map(|c| c.to_string()).collect();
+                    eprintln!("Timeout waiting for {}", conditions.join(" and "));
+                    return Ok(ExitCode::from(WAIT_TIMEOUT));
+                }
+                supply.sleep(interval.min(remaining));
+            }
+        }
+        ka3005p::cli::Command::Check {
+            file,
+            junit,
+            json,
+            keep_on,
+        } => {
+            let contents = std::fs::read_to_string(&file)
+                .with_context(|| format!("could not read {}", file.display()))?;
+            let check = toml::from_str::<CheckFile>(&contents)?;
+            let measurements = check.measurements()?;
+            let name = file
+                .file_stem()
+                .map_or("check".into(), |stem| stem.to_string_lossy());
+            if let Some(settings) = check.settings() {
+                supply.lock().apply(&settings)?;
+            }
+            // Only lock for measuring, so the watchdog is fed during the settle times
+            let report = Report::collect(&measurements, |measurement| {
+                supply.sleep(measurement.settle);
+                supply.lock().measure(measurement.quantity)
+            });
+            // Reported after the reports were written, so the measurements aren't lost
+            let switched_off = match keep_on {
+                true => Ok(()),
+                false => supply
+                    .lock()
+                    .execute(ka3005p::Command::Power(ka3005p::Switch::Off)),
+            };
+            for outcome in &report.outcomes {
+                println!("{}", outcome.summary());
+            }
+            if let Some(junit) = &junit {
+                std::fs::write(junit, report.junit(&name))
+                    .with_context(|| format!("could not write {}", junit.display()))?;
+            }
+            if let Some(json) = &json {
+                let contents = serde_json::to_string_pretty(&ReportFile::new(&name, &report))?;
+                std::fs::write(json, contents)
+                    .with_context(|| format!("could not write {}", json.display()))?;
+            }
+            switched_off.with_context(|| "could not switch the output off")?;
+            if !report.passed() {
+                return Ok(ExitCode::from(CHECK_FAILED));
+            }
+        }
+        ka3005p::cli::Command::Cycle {
+            off,
+            on,
+            count,
+            expect,
+            stop_on_failure,
+            csv,
+            keep_on,
+        } => {
+            let mut csv = match &csv {
+                Some(file) => {
+                    let file = std::fs::File::create(file)
+                        .with_context(|| format!("could not create {}", file.display()))?;
+                    let mut writer = std::io::LineWriter::new(file);
+                    writeln!(
+                        writer,
+                        "cycle,timestamp,peak_current,current_limited,voltage,current,mode,passed"
+                    )?;
+                    Some(writer)
+                }
+                None => None,
+            };
+            let cycling = ka3005p::cycle::Cycling {
+                off,
+                on,
+                count,
+                expect,
+            };
+            let mut failures = 0;
+            for number in 1..=cycling.count.unwrap_or(u32::MAX) {
+                // Only lock for each step, so the watchdog is fed while waiting
+                let cycle = cycling.run(number, |wait, step| {
+                    supply.sleep(wait);
+                    step(&mut supply.lock())
+                })?;
+                let timestamp = humantime::format_rfc3339_millis(std::time::SystemTime::now());
+                println!("{} {}", timestamp, cycle);
+                if let Some(writer) = &mut csv {
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{},{:?},{}",
+                        cycle.number,
+                        timestamp,
+                        cycle.peak_current,
+                        cycle.current_limited,
+                        cycle.status.voltage,
+                        cycle.status.current,
+                        cycle.status.flags.channel1,
+                        cycle.passed()
+                    )?;
+                }
+                if !cycle.passed() {
+                    failures += 1;
+                    if stop_on_failure {
+                        break;
+                    }
+                }
+            }
+            if !keep_on {
+                supply
+                    .lock()
+                    .execute(ka3005p::Command::Power(ka3005p::Switch::Off))?;
+            }
+            if failures > 0 {
+                eprintln!("{} cycle(s) failed", failures);
+                return Ok(ExitCode::from(CHECK_FAILED));
+            }
+        }
+        ka3005p::cli::Command::Get { value, unit } => {
+            println!("{}", value.read(&mut supply.lock(), unit)?);
+        }
+        ka3005p::cli::Command::Presets { action } => match action {
+            ka3005p::cli::Presets::Dump { file } => {
+                let presets = supply.lock().read_presets()?;
+                let contents = toml::to_string(&PresetFile::from(presets.as_slice()))?;
+                std::fs::write(&file, contents)
+                    .with_context(|| format!("could not write {}", file.display()))?;
+            }
+            ka3005p::cli::Presets::Restore { file } => {
+                let contents = std::fs::read_to_string(&file)
+                    .with_context(|| format!("could not read {}", file.display()))?;
+                let presets = toml::from_str::<PresetFile>(&contents)?.presets()?;
+                supply.lock().write_presets(&presets)?;
+            }
+        },
+        ka3005p::cli::Command::Watch {
+            interval,
+            current_threshold,
+            voltage_threshold,
+        } => {
+            let mut watcher = Watcher::new();
+            if let Some(level) = current_threshold {
+                watcher = watcher.threshold(Quantity::Current, level);
+            }
+            if let Some(level) = voltage_threshold {
+                watcher = watcher.threshold(Quantity::Voltage, level);
+            }
+            watcher.on_event(|event| {
+                println!(
+                    "{} {}",
+                    humantime::format_rfc3339_millis(std::time::SystemTime::now()),
+                    event
+                )
+            });
+            loop {
+                watcher.poll(&mut supply.lock())?;
+                supply.sleep(interval);
+            }
         }
+        ka3005p::cli::Command::Tui { interval } => tui::run(&mut supply, interval)?,
         ka3005p::cli::Command::Interactive => {
             for line in std::io::BufReader::new(std::io::stdin()).lines() {
                 let normalized = String::from(line?.trim());
                 let mut argv: Vec<&str> = normalized.split(' ').collect();
                 argv.insert(0, "ka3005p");
-                let arguments = ka3005p::cli::Ka3005p::parse_from(argv.into_iter());
-                serial.execute(
+                // Don't exit on typos, `parse_from` would skip switching the output off
+                let arguments = match ka3005p::cli::Ka3005p::try_parse_from(argv) {
+                    Ok(arguments) => arguments,
+                    Err(e) => {
+                        e.print()?;
+                        continue;
+                    }
+                };
+                supply.lock().execute(
                     arguments
                         .command
                         .try_into()
@@ -50,12 +369,12 @@ fn main() -> ::anyhow::Result<(), anyhow::Error> {
             }
         }
         _ => {
-            serial.execute(
+            supply.lock().execute(
                 args.command
                     .try_into()
                     .with_context(|| "unsupported command conversion")?,
             )?;
         }
     };
-    exit(0);
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,348 @@
+//! Terminal dashboard of `ka3005p tui`.
+use crate::Supply;
+use ka3005p::{Command, Lock, MemorySlot, Mode, Status, Switch};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph, Row, Sparkline, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of measurements kept for the sparklines, more than fit on common terminals
+const HISTORY: usize = 512;
+
+/// Step sizes for nudging the setpoints (volts, amps), cycled with tab
+const STEPS: [(f32, f32); 3] = [(0.01, 0.001), (0.1, 0.01), (1.0, 0.1)];
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Execute(Command),
+    NextStep,
+    Quit,
+}
+
+struct App {
+    identity: String,
+    memory_slots: u8,
+    status: Option<Status>,
+    /// Error of the last command
+    message: Option<String>,
+    /// Error of the last poll, cleared by the next successful one
+    poll_error: Option<String>,
+    /// Measured voltages in mV
+    voltages: VecDeque<u64>,
+    /// Measured currents in mA
+    currents: VecDeque<u64>,
+    /// The power supply doesn't report OCP/OVP, so only the states set from the dashboard are known
+    ocp: Option<Switch>,
+    ovp: Option<Switch>,
+    step: usize,
+}
+
+/// Show the dashboard until the user quits, polling the status every `interval`.
+pub fn run(supply: &mut Supply, interval: Duration) -> anyhow::Result<()> {
+    let (identity, memory_slots) = {
+        let mut dev = supply.lock();
+        let identity = dev.identify().unwrap_or_else(|_| "ka3005p".to_string());
+        (identity, dev.quirks().memory_slots)
+    };
+    let mut app = App::new(identity, memory_slots);
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal, supply, interval);
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(identity: String, memory_slots: u8) -> Self {
+        App {
+            identity,
+            memory_slots,
+            status: None,
+            message: None,
+            poll_error: None,
+            voltages: VecDeque::with_capacity(HISTORY),
+            currents: VecDeque::with_capacity(HISTORY),
+            ocp: None,
+            ovp: None,
+            step: 1,
+        }
+    }
+
+    fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        supply: &mut Supply,
+        interval: Duration,
+    ) -> anyhow::Result<()> {
+        let mut next_poll = Instant::now();
+        loop {
+            if Instant::now() >= next_poll {
+                self.poll(supply);
+                next_poll = Instant::now() + interval;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            // Wake up in time to renew the heartbeat of the watchdog
+            let heartbeat = supply.feed();
+            let wait = next_poll.saturating_duration_since(Instant::now());
+            if !event::poll(wait.min(heartbeat))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match self.action(key) {
+                Some(Action::Quit) => return Ok(()),
+                Some(Action::NextStep) => self.step = (self.step + 1) % STEPS.len(),
+                Some(Action::Execute(command)) => {
+                    self.execute(supply, command);
+                    // Show the effect right away
+                    next_poll = Instant::now();
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn poll(&mut self, supply: &mut Supply) {
+        let status = supply.lock().status();
+        self.update(status);
+    }
+
+    fn update(&mut self, status: anyhow::Result<Status>) {
+        match status {
+            Ok(status) => {
+                push(&mut self.voltages, status.voltage * 1000.0);
+                push(&mut self.currents, status.current * 1000.0);
+                self.status = Some(status);
+                self.poll_error = None;
+            }
+            Err(e) => self.poll_error = Some(format!("Could not read the status: {:#}", e)),
+        }
+    }
+
+    fn execute(&mut self, supply: &mut Supply, command: Command) {
+        match supply.lock().execute(command) {
+            Ok(()) => {
+                match command {
+                    Command::Ocp(switch) => self.ocp = Some(switch),
+                    Command::Ovp(switch) => self.ovp = Some(switch),
+                    _ => {}
+                }
+                self.message = None;
+            }
+            Err(e) => self.message = Some(format!("{:?} failed: {:#}", command, e)),
+        }
+    }
+
+    fn action(&self, key: KeyEvent) -> Option<Action> {
+        let (volts, amps) = STEPS[self.step];
+        let status = self.status.as_ref();
+        let command = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Tab => return Some(Action::NextStep),
+            KeyCode::Up => Command::Voltage(nudge(status?.set_voltage, volts)),
+            KeyCode::Down => Command::Voltage(nudge(status?.set_voltage, -volts)),
+            KeyCode::Right => Command::Current(nudge(status?.set_current, amps)),
+            KeyCode::Left => Command::Current(nudge(status?.set_current, -amps)),
+            KeyCode::Char('o') => Command::Power(toggle(status?.flags.output)),
+            KeyCode::Char('b') => Command::Beep(toggle(status?.flags.beep)),
+            KeyCode::Char('c') => Command::Ocp(toggle(self.ocp.unwrap_or(Switch::Off))),
+            KeyCode::Char('v') => Command::Ovp(toggle(self.ovp.unwrap_or(Switch::Off))),
+            KeyCode::Char(digit @ '1'..='9') => {
+                let slot = digit.to_digit(10)? as u8;
+                if slot > self.memory_slots {
+                    return None;
+                }
+                Command::Load(MemorySlot::new(slot).ok()?)
+            }
+            _ => return None,
+        };
+        Some(Action::Execute(command))
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [readings, voltages, currents, footer] = Layout::vertical([
+            Constraint::Length(7),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        frame.render_widget(self.readings(), readings);
+        render_history(frame, "Voltage", &self.voltages, Color::Yellow, voltages);
+        render_history(frame, "Current", &self.currents, Color::Cyan, currents);
+        let footer_line = match self.message.as_ref().or(self.poll_error.as_ref()) {
+            Some(message) => Line::from(message.as_str()).red(),
+            None => Line::from(format!(
+                "q quit  ↑/↓ voltage ±{}V  ←/→ current ±{}A  tab step  o output  b beep  c OCP  v OVP  1-{} recall",
+                STEPS[self.step].0, STEPS[self.step].1, self.memory_slots
+            ))
+            .dark_gray(),
+        };
+        frame.render_widget(Paragraph::new(footer_line), footer);
+    }
+
+    fn readings(&self) -> Table<'_> {
+        let block = Block::bordered().title(format!(" {} ", self.identity));
+        let Some(status) = &self.status else {
+            return Table::new(
+                [Row::new(["Waiting for the power supply…"])],
+                [Constraint::Fill(1)],
+            )
+            .block(block);
+        };
+        let flags = &status.flags;
+        let mode = match flags.channel1 {
+            Mode::Cc => Span::from("CC").red().bold(),
+            Mode::Cv => Span::from("CV").green().bold(),
+        };
+        let rows = [
+            Row::new(["", "Measured", "Set"]).dark_gray(),
+            Row::new([
+                "Voltage".to_string(),
+                format!("{:.2} V", status.voltage),
+                format!("{:.2} V", status.set_voltage),
+            ]),
+            Row::new([
+                "Current".to_string(),
+                format!("{:.3} A", status.current),
+                format!("{:.3} A", status.set_current),
+            ]),
+            Row::new([Line::from(""), Line::from("")]),
+            Row::new([
+                Line::from(vec!["Mode ".into(), mode]),
+                Line::from(vec!["Output ".into(), on_off(Some(flags.output))]),
+                Line::from(vec![
+                    "Lock ".into(),
+                    on_off(Some(Switch::from(flags.lock == Lock::Locked))),
+                ]),
+                Line::from(vec!["Beep ".into(), on_off(Some(flags.beep))]),
+                Line::from(vec!["OCP ".into(), on_off(self.ocp)]),
+                Line::from(vec!["OVP ".into(), on_off(self.ovp)]),
+            ]),
+        ];
+        Table::new(rows, [Constraint::Length(12); 6]).block(block)
+    }
+}
+
+fn render_history(
+    frame: &mut Frame,
+    title: &str,
+    history: &VecDeque<u64>,
+    color: Color,
+    area: Rect,
+) {
+    // Only the most recent measurements which fit into the block
+    let width = usize::from(area.width.saturating_sub(2));
+    let data: Vec<u64> = history
+        .iter()
+        .skip(history.len().saturating_sub(width))
+        .copied()
+        .collect();
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title(format!(" {} ", title)))
+        .data(&data)
+        .style(Style::default().fg(color));
+    frame.render_widget(sparkline, area);
+}
+
+fn push(history: &mut VecDeque<u64>, value: f32) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value.max(0.0).round() as u64);
+}
+
+/// Change a setpoint by `step`, avoiding float noise like 5.0999999 and negative values.
+fn nudge(value: f32, step: f32) -> f32 {
+    ((value + step) * 1000.0).round().max(0.0) / 1000.0
+}
+
+fn toggle(switch: Switch) -> Switch {
+    Switch::from(!bool::from(switch))
+}
+
+fn on_off(switch: Option<Switch>) -> Span<'static> {
+    match switch {
+        Some(Switch::On) => Span::from("on").green(),
+        Some(Switch::Off) => Span::from("off").dark_gray(),
+        None => Span::from("?").dark_gray(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::crossterm::event::KeyModifiers;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut app = App::new("KORAD KA3005P V5.8".to_string(), 5);
+        assert_eq!(app.action(key(KeyCode::Up)), None);
+        assert_eq!(app.action(key(KeyCode::Char('q'))), Some(Action::Quit));
+        app.status = Some(Status {
+            flags: 0x51.into(),
+            voltage: 4.99,
+            current: 0.1,
+            set_voltage: 5.0,
+            set_current: 1.0,
+        });
+        assert_eq!(
+            app.action(key(KeyCode::Up)),
+            Some(Action::Execute(Command::Voltage(5.1)))
+        );
+        assert_eq!(
+            app.action(key(KeyCode::Left)),
+            Some(Action::Execute(Command::Current(0.99)))
+        );
+        assert_eq!(
+            app.action(key(KeyCode::Char('o'))),
+            Some(Action::Execute(Command::Power(Switch::Off)))
+        );
+        assert_eq!(
+            app.action(key(KeyCode::Char('c'))),
+            Some(Action::Execute(Command::Ocp(Switch::On)))
+        );
+        assert_eq!(
+            app.action(key(KeyCode::Char('2'))),
+            Some(Action::Execute(Command::Load(MemorySlot::new(2).unwrap())))
+        );
+        assert_eq!(app.action(key(KeyCode::Char('6'))), None);
+    }
+
+    #[test]
+    fn test_poll_error_is_cleared() {
+        let mut app = App::new("KORAD KA3005P V5.8".to_string(), 5);
+        app.update(Err(anyhow::anyhow!("PSU did not respond with data")));
+        assert!(app.poll_error.is_some());
+        app.message = Some("Voltage(5.0) failed".to_string());
+        app.update(Ok(Status {
+            flags: 0x51.into(),
+            voltage: 4.99,
+            current: 0.1,
+            set_voltage: 5.0,
+            set_current: 1.0,
+        }));
+        assert_eq!(app.poll_error, None);
+        assert_eq!(app.voltages, [4990]);
+        // Errors of commands stay until the next command
+        assert!(app.message.is_some());
+    }
+
+    #[test]
+    fn test_nudge() {
+        assert_eq!(nudge(5.0, 0.1), 5.1);
+        assert_eq!(nudge(0.005, -0.01), 0.0);
+        assert_eq!(nudge(1.0, -0.001), 0.999);
+    }
+}
@@ -0,0 +1,242 @@
+//! Builder for power supplies which need non-default connection settings.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::Ka3005p;
+//!
+//! let mut dev = Ka3005p::builder()
+//!     .port_name("/dev/ttyACM0")
+//!     .timeout(Duration::from_millis(100))
+//!     .command_delay(Duration::from_millis(50))
+//!     .retries(2)
+//!     .open()
+//!     .unwrap();
+//! ```
+use crate::quirks::Quirks;
+use crate::reconnect::Reconnect;
+use crate::slew::SlewRate;
+use crate::{list_serial_ports, Error, Ka3005p};
+use log::{debug, info};
+use serialport::{Parity, SerialPort, StopBits};
+use std::time::Duration;
+
+/// Connection settings which are kept by the [`Ka3005p`] object, e.g. to reopen the port.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Settings {
+    pub(crate) baud_rate: u32,
+    pub(crate) timeout: Duration,
+    pub(crate) parity: Parity,
+    pub(crate) stop_bits: StopBits,
+    pub(crate) command_delay: Duration,
+    pub(crate) terminator: String,
+    pub(crate) retries: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            baud_rate: 9600,
+            timeout: Duration::from_millis(60),
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            command_delay: Duration::ZERO,
+            terminator: String::new(),
+            retries: 0,
+        }
+    }
+}
+
+impl Settings {
+    pub(crate) fn open(&self, port_name: &str) -> anyhow::Result<Box<dyn SerialPort>> {
+        let serial = serialport::new(port_name, self.baud_rate)
+            .timeout(self.timeout)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .open()?;
+        Ok(serial)
+    }
+}
+
+/// Configures and opens a connection to a power supply, see [`Ka3005p::builder`].
+#[derive(Debug, Default, Clone)]
+pub struct Ka3005pBuilder {
+    port_name: Option<String>,
+    settings: Settings,
+    reconnect: Option<Reconnect>,
+    quirks: Option<Quirks>,
+    slew_rate: Option<SlewRate>,
+}
+
+impl Ka3005pBuilder {
+    /// Serial port of the power supply. If not set the first power supply found is used.
+    pub fn port_name(mut self, port_name: &str) -> Self {
+        self.port_name = Some(port_name.to_string());
+        self
+    }
+
+    /// Baud rate of the serial port (default: 9600)
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.settings.baud_rate = baud_rate;
+        self
+    }
+
+    /// How long to wait for a response from the power supply (default: 60 ms).
+    /// Responses are considered complete once no further data arrives within this time.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.settings.timeout = timeout;
+        self
+    }
+
+    /// Parity of the serial port (default: none)
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.settings.parity = parity;
+        self
+    }
+
+    /// Stop bits of the serial port (default: one)
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.settings.stop_bits = stop_bits;
+        self
+    }
+
+    /// Minimum time between two commands (default: none).
+    /// Some clones drop commands which are sent back-to-back.
+    pub fn command_delay(mut self, delay: Duration) -> Self {
+        self.settings.command_delay = delay;
+        self
+    }
+
+    /// Terminator appended to every command, e.g. `"\n"` (default: none)
+    pub fn terminator(mut self, terminator: &str) -> Self {
+        self.settings.terminator = terminator.to_string();
+        self
+    }
+
+    /// How often a query is repeated if the power supply didn't respond with valid data (default: 0)
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.settings.retries = retries;
+        self
+    }
+
+    /// Reconnect automatically if the connection is lost, see [`Ka3005p::set_reconnect`]
+    pub fn reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = Some(reconnect);
+        self
+    }
+
+    /// Use the given quirks instead of detecting them from the `*IDN?` response
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Limit how fast the voltage and current change, see [`Ka3005p::set_slew_rate`]
+    pub fn slew_rate(mut self, slew_rate: SlewRate) -> Self {
+        self.slew_rate = Some(slew_rate);
+        self
+    }
+
+    /// Open the connection to the power supply.
+    pub fn open(self) -> anyhow::Result<Ka3005p> {
+        let port_name = self.resolve_port_name()?;
+        let serial = self.settings.open(&port_name)?;
+        let mut supply = Ka3005p::with_serial(serial, self.settings);
+        supply.identity = crate::reconnect::usb_identity(&port_name);
+        supply.port_name = Some(port_name);
+        supply.set_reconnect(self.reconnect);
+        supply.set_slew_rate(self.slew_rate);
+        match self.quirks {
+            Some(quirks) => supply.set_quirks(quirks),
+            None => match supply.identify() {
+                Ok(identity) => {
+                    info!("Connected to {}", identity);
+                    supply.set_quirks(Quirks::for_identity(&identity));
+                }
+                Err(e) => debug!("Could not identify the power supply: {:#}", e),
+            },
+        }
+        Ok(supply)
+    }
+
+    /// Open only the serial port of the power supply, e.g. to forward raw traffic.
+    pub fn open_port(self) -> anyhow::Result<Box<dyn SerialPort>> {
+        let port_name = self.resolve_port_name()?;
+        self.settings.open(&port_name)
+    }
+
+    fn resolve_port_name(&self) -> anyhow::Result<String> {
+        match &self.port_name {
+            Some(port_name) => Ok(port_name.clone()),
+            None => list_serial_ports()
+                .into_iter()
+                .next()
+                .map(|info| info.port_name)
+                .ok_or_else(|| Error::DeviceNotFound("No Power Supply Found!".to_string()).into()),
+        }
+    }
+
+    /// Use an already opened serial port, only the settings which don't affect the
+    /// serial port itself are applied (command delay, terminator, retries, quirks, slew rate).
+    /// The quirks are not detected automatically.
+    pub fn open_serial(self, serial: Box<dyn SerialPort>) -> Ka3005p {
+        let mut supply = Ka3005p::with_serial(serial, self.settings);
+        supply.set_quirks(self.quirks.unwrap_or_default());
+        supply.set_slew_rate(self.slew_rate);
+        supply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_settings() {
+        let builder = Ka3005p::builder();
+        assert_eq!(builder.settings, Settings::default());
+        assert_eq!(builder.port_name, None);
+        let builder = builder
+            .port_name("/dev/ttyACM0")
+            .baud_rate(115200)
+            .timeout(Duration::from_millis(100))
+            .command_delay(Duration::from_millis(50))
+            .terminator("\n")
+            .retries(2);
+        assert_eq!(builder.port_name.as_deref(), Some("/dev/ttyACM0"));
+        assert_eq!(builder.resolve_port_name().unwrap(), "/dev/ttyACM0");
+        assert_eq!(
+            builder.settings,
+            Settings {
+                baud_rate: 115200,
+                timeout: Duration::from_millis(100),
+                command_delay: Duration::from_millis(50),
+                terminator: "\n".to_string(),
+                retries: 2,
+                ..Settings::default()
+            }
+        );
+        assert!(Ka3005p::builder()
+            .port_name("/dev/does-not-exist")
+            .open()
+            .is_err());
+    }
+
+    #[test]
+    fn test_open_serial() {
+        let mock = MockPort::new();
+        mock.once("VSET1?", Reply::Timeout)
+            .on("VSET1?", Reply::data("5.000"));
+        let quirks = Quirks::for_identity("72-2550");
+        let mut dev = Ka3005p::builder()
+            .retries(1)
+            .quirks(quirks)
+            .open_serial(Box::new(mock.clone()));
+        assert_eq!(dev.quirks(), &quirks);
+        // The first query times out and is repeated
+        assert_eq!(dev.read_set_voltage().unwrap(), 5.0);
+        assert_eq!(mock.requests(), ["VSET1?", "VSET1?"]);
+        let dev = Ka3005p::builder().open_serial(Box::new(MockPort::new()));
+        assert_eq!(dev.quirks(), &Quirks::default());
+    }
+}
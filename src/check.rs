@@ -0,0 +1,339 @@
+//! Pass/fail measurements against limits, e.g. for end-of-line tests, with JUnit XML reports.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::check::Measurement;
+//! use ka3005p::watch::Quantity;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let measurements = [Measurement {
+//!     name: "quiescent current".to_string(),
+//!     quantity: Quantity::Current,
+//!     min: None,
+//!     max: Some(0.02),
+//!     settle: Duration::from_millis(500),
+//! }];
+//! let report = dev.check(&measurements);
+//! std::fs::write("report.xml", report.junit("board")).unwrap();
+//! assert!(report.passed());
+//! ```
+use crate::watch::Quantity;
+use crate::Ka3005p;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A measurement with its limits
+#[derive(Debug, PartialEq, Clone)]
+pub struct Measurement {
+    /// Name of the measurement, e.g. "quiescent current"
+    pub name: String,
+    /// The measured quantity
+    pub quantity: Quantity,
+    /// Lowest value which passes
+    pub min: Option<f32>,
+    /// Highest value which passes
+    pub max: Option<f32>,
+    /// Time to wait before measuring
+    pub settle: Duration,
+}
+
+/// Result of a [`Measurement`]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Outcome {
+    /// The measurement
+    pub measurement: Measurement,
+    /// The measured value, or why it couldn't be measured
+    pub value: Result<f32, String>,
+    /// Time taken including the settle time
+    pub time: Duration,
+}
+
+impl Outcome {
+    /// Whether the value was measured and within the limits
+    pub fn passed(&self) -> bool {
+        self.value.is_ok() && self.failure().is_none()
+    }
+
+    /// Why the measured value isn't within the limits
+    pub fn failure(&self) -> Option<String> {
+        let value = *self.value.as_ref().ok()?;
+        let unit = unit(self.measurement.quantity);
+        match (self.measurement.min, self.measurement.max) {
+            (Some(min), _) if value < min => Some(format!(
+                "{} {} is below the minimum of {} {}",
+                value, unit, min, unit
+            )),
+            (_, Some(max)) if value > max => Some(format!(
+                "{} {} is above the maximum of {} {}",
+                value, unit, max, unit
+            )),
+            _ => None,
+        }
+    }
+
+    /// One line summary, e.g. "PASS quiescent current: 0.012 A (max 0.02 A)"
+    pub fn summary(&self) -> String {
+        let verdict = match (&self.value, self.passed()) {
+            (Err(_), _) => "ERROR",
+            (Ok(_), true) => "PASS",
+            (Ok(_), false) => "FAIL",
+        };
+        let value = match &self.value {
+            Ok(value) => format!("{} {}", value, unit(self.measurement.quantity)),
+            Err(error) => error.clone(),
+        };
+        format!(
+            "{} {}: {} ({})",
+            verdict,
+            self.measurement.name,
+            value,
+            self.limits()
+        )
+    }
+
+    fn limits(&self) -> String {
+        let unit = unit(self.measurement.quantity);
+        match (self.measurement.min, self.measurement.max) {
+            (Some(min), Some(max)) => format!("{} {} .. {} {}", min, unit, max, unit),
+            (Some(min), None) => format!("min {} {}", min, unit),
+            (None, Some(max)) => format!("max {} {}", max, unit),
+            (None, None) => "no limits".to_string(),
+        }
+    }
+}
+
+/// Outcomes of all measurements of a check
+#[derive(Debug, PartialEq, Clone)]
+pub struct Report {
+    /// When the check started
+    pub start: SystemTime,
+    /// Outcomes in the order of the measurements
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    /// Whether all measurements passed
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(Outcome::passed)
+    }
+
+    /// The report in the JUnit XML format, each measurement is a test case of the suite `name`.
+    pub fn junit(&self, name: &str) -> String {
+        let tests = self.outcomes.len();
+        let errors = self.outcomes.iter().filter(|o| o.value.is_err()).count();
+        let failures = self
+            .outcomes
+            .iter()
+            .filter(|o| o.failure().is_some())
+            .count();
+        let time: Duration = self.outcomes.iter().map(|o| o.time).sum();
+        let counts = format!(
+            r#"tests="{}" failures="{}" errors="{}" time="{:.3}""#,
+            tests,
+            failures,
+            errors,
+            time.as_secs_f64()
+        );
+        let mut lines = vec![
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+            format!(r#"<testsuites name="ka3005p" {}>"#, counts),
+            format!(
+                r#"  <testsuite name="{}" {} timestamp="{}">"#,
+                escape(name),
+                counts,
+                rfc3339(self.start)
+            ),
+        ];
+        for outcome in &self.outcomes {
+            lines.push(format!(
+                r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+                escape(&outcome.measurement.name),
+                escape(name),
+                outcome.time.as_secs_f64()
+            ));
+            match (&outcome.value, outcome.failure()) {
+                (Err(error), _) => {
+                    lines.push(format!(r#"      <error message="{}"/>"#, escape(error)))
+                }
+                (Ok(_), Some(failure)) => lines.push(format!(
+                    r#"      <failure message="{}"/>"#,
+                    escape(&failure)
+                )),
+                _ => {}
+            }
+            lines.push(format!(
+                "      <system-out>{}</system-out>",
+                escape(&outcome.summary())
+            ));
+            lines.push("    </testcase>".to_string());
+        }
+        lines.push("  </testsuite>".to_string());
+        lines.push("</testsuites>\n".to_string());
+        lines.join("\n")
+    }
+}
+
+impl Report {
+    /// Carry out `measurements` with `measure`, which waits for the settle time and measures the value,
+    /// e.g. to release the power supply while waiting. See [`Ka3005p::check`].
+    pub fn collect(
+        measurements: &[Measurement],
+        mut measure: impl FnMut(&Measurement) -> anyhow::Result<f32>,
+    ) -> Self {
+        let start = SystemTime::now();
+        let outcomes = measurements
+            .iter()
+            .map(|measurement| {
+                let begin = Instant::now();
+                let value = measure(measurement).map_err(|e| format!("{:#}", e));
+                Outcome {
+                    measurement: measurement.clone(),
+                    value,
+                    time: begin.elapsed(),
+                }
+            })
+            .collect();
+        Report { start, outcomes }
+    }
+}
+
+impl Ka3005p {
+    /// Wait for the settle time of each measurement, measure it and compare it with its limits.
+    ///
+    /// Errors (e.g. timeouts) are recorded in the outcome of the affected measurement,
+    /// the remaining measurements are still carried out.
+    pub fn check(&mut self, measurements: &[Measurement]) -> Report {
+        Report::collect(measurements, |measurement| {
+            thread::sleep(measurement.settle);
+            self.measure(measurement.quantity)
+        })
+    }
+
+    /// Measure a quantity, the power is calculated from the measured voltage and current.
+    pub fn measure(&mut self, quantity: Quantity) -> anyhow::Result<f32> {
+        Ok(match quantity {
+            Quantity::Voltage => self.read_measured_voltage()?,
+            Quantity::Current => self.read_measured_current()?,
+            Quantity::Power => {
+                let power = self.read_measured_voltage()? * self.read_measured_current()?;
+                // Round away the noise of the multiplication, e.g. 0.49899998 for 4.99 V * 0.1 A
+                let decimals = self.quirks.voltage_decimals + self.quirks.current_decimals;
+                let scale = 10f32.powi(decimals as i32);
+                (power * scale).round() / scale
+            }
+        })
+    }
+}
+
+fn unit(quantity: Quantity) -> &'static str {
+    match quantity {
+        Quantity::Voltage => "V",
+        Quantity::Current => "A",
+        Quantity::Power => "W",
+    }
+}
+
+/// Format a time as RFC 3339 in UTC, e.g. `2024-02-29T12:35:03Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+    // Civil date from the days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    fn measurement(
+        name: &str,
+        quantity: Quantity,
+        min: Option<f32>,
+        max: Option<f32>,
+    ) -> Measurement {
+        Measurement {
+            name: name.to_string(),
+            quantity,
+            min,
+            max,
+            settle: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mock = MockPort::new();
+        mock.on("IOUT1?", Reply::data("0.012"));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock)).unwrap();
+        let report = dev.check(&[
+            measurement("quiescent current", Quantity::Current, None, Some(0.02)),
+            measurement("active current", Quantity::Current, Some(0.1), Some(0.5)),
+            measurement("rail", Quantity::Voltage, Some(3.2), None),
+        ]);
+        assert!(!report.passed());
+        let summaries: Vec<String> = report.outcomes.iter().map(Outcome::summary).collect();
+        assert_eq!(summaries[0], "PASS quiescent current: 0.012 A (max 0.02 A)");
+        assert_eq!(
+            summaries[1],
+            "FAIL active current: 0.012 A (0.1 A .. 0.5 A)"
+        );
+        assert!(summaries[2].starts_with("ERROR rail: "));
+    }
+
+    #[test]
+    fn test_junit() {
+        let outcome = |value, min| Outcome {
+            measurement: measurement("<current> & \"more\"", Quantity::Current, min, None),
+            value,
+            time: Duration::from_millis(250),
+        };
+        let report = Report {
+            start: SystemTime::UNIX_EPOCH,
+            outcomes: vec![
+                outcome(Ok(0.5), Some(0.1)),
+                outcome(Ok(0.05), Some(0.1)),
+                outcome(Err("PSU did not respond with data".to_string()), None),
+            ],
+        };
+        let xml = report.junit("board");
+        assert!(xml.contains(
+            r#"<testsuite name="board" tests="3" failures="1" errors="1" time="0.750" timestamp="1970-01-01T00:00:00Z">"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase name="&lt;current&gt; &amp; &quot;more&quot;" classname="board" time="0.250">"#
+        ));
+        assert!(xml.contains(r#"<failure message="0.05 A is below the minimum of 0.1 A"/>"#));
+        assert!(xml.contains(r#"<error message="PSU did not respond with data"/>"#));
+        assert_eq!(xml.matches("</testcase>").count(), 3);
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_secs(1709210103)),
+            "2024-02-29T12:35:03Z"
+        );
+    }
+}
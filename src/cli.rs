@@ -1,7 +1,8 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use std::clone::Clone;
 
-#[derive(Copy, Clone, PartialEq, Subcommand)]
+#[derive(Clone, PartialEq, Subcommand)]
 pub enum Command {
     /// Turns on or off the ouput of the power supply
     Power {
@@ -11,6 +12,16 @@ pub enum Command {
     },
     /// Return status inforation about the power spply
     Status,
+    /// Print a single value, e.g. for shell scripts
+    ///
+    /// Exit codes: 0 success, 1 other errors, 2 invalid arguments, 3 no power supply found,
+    /// 4 the power supply didn't respond, 5 invalid response, 6 timeout of wait-for, 7 check or cycle failed
+    Get {
+        value: Value,
+        /// Append the unit (V or A) to voltages and currents
+        #[clap(short, long)]
+        unit: bool,
+    },
     /// Set the voltage of the ouput or config
     Voltage {
         #[clap(help = "volts")]
@@ -21,15 +32,40 @@ pub enum Command {
         #[clap(help = "ampere")]
         a: f32,
     },
+    /// Apply several settings at once, verify them and print the resulting status
+    ///
+    /// The output is switched off while the limits change and switched on afterwards if requested
+    /// (or if it was on before).
+    #[clap(group(clap::ArgGroup::new("settings").required(true).multiple(true)))]
+    Apply {
+        /// Target voltage in volts
+        #[clap(long, group = "settings")]
+        voltage: Option<f32>,
+        /// Current limit in amps
+        #[clap(long, group = "settings")]
+        current: Option<f32>,
+        /// Over current protection (on/off)
+        #[clap(long, group = "settings")]
+        ocp: Option<crate::Switch>,
+        /// Over voltage protection (on/off)
+        #[clap(long, group = "settings")]
+        ovp: Option<crate::Switch>,
+        /// Beep (on/off)
+        #[clap(long, group = "settings")]
+        beep: Option<crate::Switch>,
+        /// Output (on/off), applied last
+        #[clap(long, group = "settings")]
+        output: Option<crate::Switch>,
+    },
     /// Saves current pannel settings to specified config
     Save {
-        #[clap(help = "1,2,3,4")]
-        id: u32,
+        #[clap(help = "1,2,3,4,5")]
+        id: crate::MemorySlot,
     },
     /// Loads config settings of specified no.
     Load {
-        #[clap(help = "1,2,3,4")]
-        id: u32,
+        #[clap(help = "1,2,3,4,5")]
+        id: crate::MemorySlot,
     },
     /// Enable/Disable over current protection
     Ocp {
@@ -54,6 +90,323 @@ pub enum Command {
     },
     /// Read commands from stdin and execute them
     Interactive,
+    /// Backup or restore the settings stored in the memory slots
+    Presets {
+        #[clap(subcommand)]
+        action: Presets,
+    },
+    /// Print state changes (CC/CV mode, output, lock, thresholds) as they happen
+    Watch {
+        /// Time between two status queries (e.g. 500ms)
+        #[clap(short, long, default_value = "500ms", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+        /// Report when the measured current crosses this level (ampere)
+        #[clap(long)]
+        current_threshold: Option<f32>,
+        /// Report when the measured voltage crosses this level (volts)
+        #[clap(long)]
+        voltage_threshold: Option<f32>,
+    },
+    /// Wait until all conditions hold, e.g. 'current < 0.02' or 'mode != CC'
+    ///
+    /// Conditions compare voltage, current or power (W) with <, <=, > or >=,
+    /// or mode (CV/CC) and output (on/off) with == or !=.
+    /// Exits with 6 if the conditions didn't hold within the timeout.
+    WaitFor {
+        #[clap(required = true)]
+        conditions: Vec<crate::condition::Condition>,
+        /// Give up after this time (e.g. 30s), waits forever if not set
+        #[clap(short, long, value_parser = humantime::parse_duration)]
+        timeout: Option<std::time::Duration>,
+        /// Time between two status queries (e.g. 200ms)
+        #[clap(short, long, default_value = "200ms", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+    },
+    /// Measure against the limits from a TOML file and report pass/fail
+    ///
+    /// Exits with 7 if a measurement failed. The output is switched off afterwards unless --keep-on is given.
+    Check {
+        #[clap(help = "toml file")]
+        file: std::path::PathBuf,
+        /// Write a JUnit XML report
+        #[clap(long)]
+        junit: Option<std::path::PathBuf>,
+        /// Write a JSON report
+        #[clap(long)]
+        json: Option<std::path::PathBuf>,
+        /// Leave the output on after the measurements
+        #[clap(long)]
+        keep_on: bool,
+    },
+    /// Power cycle the output repeatedly and log the measurements of each cycle
+    ///
+    /// Exits with 7 if an expected condition didn't hold in a cycle.
+    /// The output is switched off after the last cycle unless --keep-on is given.
+    Cycle {
+        /// How long the output is off in each cycle (e.g. 2s)
+        #[clap(long, value_parser = humantime::parse_duration)]
+        off: std::time::Duration,
+        /// How long the output is on in each cycle (e.g. 10s)
+        #[clap(long, value_parser = humantime::parse_duration)]
+        on: std::time::Duration,
+        /// Number of cycles, cycles until interrupted if not set
+        #[clap(short, long)]
+        count: Option<u32>,
+        /// Condition which has to hold at the end of each on phase, e.g. 'current > 0.05' (see wait-for)
+        #[clap(long)]
+        expect: Vec<crate::condition::Condition>,
+        /// Stop at the first cycle in which an expected condition didn't hold
+        #[clap(long)]
+        stop_on_failure: bool,
+        /// Also write the measurements of each cycle to a CSV file
+        #[clap(long)]
+        csv: Option<std::path::PathBuf>,
+        /// Leave the output on after the last cycle
+        #[clap(long)]
+        keep_on: bool,
+    },
+    /// Live dashboard to monitor and control the power supply
+    Tui {
+        /// Time between two status queries (e.g. 250ms)
+        #[clap(short, long, default_value = "250ms", value_parser = humantime::parse_duration)]
+        interval: std::time::Duration,
+    },
+    /// Forward the traffic of another program to the power supply and print it decoded
+    Proxy {
+        /// Create a pseudo terminal for the other program
+        #[clap(long, required_unless_present = "host")]
+        pty: bool,
+        /// Serial port the other program is connected to, e.g. one end of a virtual null modem
+        #[clap(long, conflicts_with = "pty")]
+        host: Option<String>,
+    },
+}
+
+/// A value which can be read with `get`
+#[derive(Copy, Clone, PartialEq, ValueEnum)]
+pub enum Value {
+    /// Measured voltage
+    Voltage,
+    /// Measured current
+    Current,
+    /// Target voltage
+    SetVoltage,
+    /// Current limit
+    SetCurrent,
+    /// Output state (on/off)
+    Output,
+    /// Mode of channel 1 (CV/CC)
+    Mode,
+    /// Lock state of the front panel (on/off)
+    Lock,
+    /// Beep state (on/off)
+    Beep,
+}
+
+impl Value {
+    /// Read the value from the power supply, formatted with the precision of the model
+    pub fn read(self, dev: &mut crate::Ka3005p, unit: bool) -> anyhow::Result<String> {
+        let quirks = dev.quirks();
+        let (voltage_decimals, current_decimals) =
+            (quirks.voltage_decimals, quirks.current_decimals);
+        let number = |value: f32, decimals: usize, symbol: &str| match unit {
+            true => format!("{:.*} {}", decimals, value, symbol),
+            false => format!("{:.*}", decimals, value),
+        };
+        let switch = |switch: crate::Switch| match switch {
+            crate::Switch::On => "on".to_string(),
+            crate::Switch::Off => "off".to_string(),
+        };
+        Ok(match self {
+            Value::Voltage => number(dev.read_measured_voltage()?, voltage_decimals, "V"),
+            Value::Current => number(dev.read_measured_current()?, current_decimals, "A"),
+            Value::SetVoltage => number(dev.read_set_voltage()?, voltage_decimals, "V"),
+            Value::SetCurrent => number(dev.read_set_current()?, current_decimals, "A"),
+            Value::Output => switch(dev.read_output_enable()?.into()),
+            Value::Mode => match dev.read_flags()?.channel1 {
+                crate::Mode::Cc => "CC".to_string(),
+                crate::Mode::Cv => "CV".to_string(),
+            },
+            Value::Lock => switch((dev.read_flags()?.lock == crate::Lock::Locked).into()),
+            Value::Beep => switch(dev.read_flags()?.beep),
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Subcommand)]
+pub enum Presets {
+    /// Write the settings of all memory slots to a file. Note: This switches the output off
+    Dump {
+        #[clap(help = "toml file")]
+        file: std::path::PathBuf,
+    },
+    /// Store the settings from a file in the memory slots
+    Restore {
+        #[clap(help = "toml file")]
+        file: std::path::PathBuf,
+    },
+}
+
+/// Contents of a file created by `presets dump`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PresetFile {
+    pub preset: Vec<PresetEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PresetEntry {
+    pub slot: u8,
+    pub voltage: f32,
+    pub current: f32,
+}
+
+impl From<&[crate::presets::Preset]> for PresetFile {
+    fn from(presets: &[crate::presets::Preset]) -> Self {
+        PresetFile {
+            preset: presets
+                .iter()
+                .map(|preset| PresetEntry {
+                    slot: preset.slot.get(),
+                    voltage: preset.voltage,
+                    current: preset.current,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PresetFile {
+    pub fn presets(&self) -> anyhow::Result<Vec<crate::presets::Preset>> {
+        self.preset
+            .iter()
+            .map(|entry| {
+                Ok(crate::presets::Preset {
+                    slot: crate::MemorySlot::new(entry.slot)?,
+                    voltage: entry.voltage,
+                    current: entry.current,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Contents of a file for `check`
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct CheckFile {
+    /// Settings applied before the measurements
+    pub setup: Option<SetupEntry>,
+    pub measurement: Vec<MeasurementEntry>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct SetupEntry {
+    pub voltage: Option<f32>,
+    pub current: Option<f32>,
+    pub ocp: Option<bool>,
+    pub ovp: Option<bool>,
+    pub output: Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct MeasurementEntry {
+    pub name: String,
+    /// voltage, current or power
+    pub quantity: String,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    /// Time to wait before measuring, e.g. "500ms"
+    pub settle: Option<String>,
+}
+
+impl CheckFile {
+    pub fn settings(&self) -> Option<crate::settings::Settings> {
+        self.setup.as_ref().map(|setup| crate::settings::Settings {
+            voltage: setup.voltage,
+            current: setup.current,
+            ocp: setup.ocp.map(crate::Switch::from),
+            ovp: setup.ovp.map(crate::Switch::from),
+            beep: None,
+            output: setup.output.map(crate::Switch::from),
+        })
+    }
+
+    pub fn measurements(&self) -> anyhow::Result<Vec<crate::check::Measurement>> {
+        use crate::watch::Quantity;
+        self.measurement
+            .iter()
+            .map(|entry| {
+                let quantity = match entry.quantity.to_lowercase().as_str() {
+                    "voltage" => Quantity::Voltage,
+                    "current" => Quantity::Current,
+                    "power" => Quantity::Power,
+                    _ => anyhow::bail!(
+                        "Invalid quantity {:?} of {:?}, expected voltage, current or power",
+                        entry.quantity,
+                        entry.name
+                    ),
+                };
+                let settle = match &entry.settle {
+                    Some(settle) => humantime::parse_duration(settle).map_err(|e| {
+                        anyhow::anyhow!("Invalid settle time of {:?}: {}", entry.name, e)
+                    })?,
+                    None => std::time::Duration::ZERO,
+                };
+                Ok(crate::check::Measurement {
+                    name: entry.name.clone(),
+                    quantity,
+                    min: entry.min,
+                    max: entry.max,
+                    settle,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Contents of the JSON report written by `check`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ReportFile {
+    pub name: String,
+    pub timestamp: String,
+    pub passed: bool,
+    pub measurements: Vec<OutcomeEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OutcomeEntry {
+    pub name: String,
+    pub quantity: String,
+    pub value: Option<f32>,
+    pub error: Option<String>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub passed: bool,
+    /// Seconds including the settle time
+    pub time: f64,
+}
+
+impl ReportFile {
+    pub fn new(name: &str, report: &crate::check::Report) -> Self {
+        ReportFile {
+            name: name.to_string(),
+            timestamp: humantime::format_rfc3339_seconds(report.start).to_string(),
+            passed: report.passed(),
+            measurements: report
+                .outcomes
+                .iter()
+                .map(|outcome| OutcomeEntry {
+                    name: outcome.measurement.name.clone(),
+                    quantity: format!("{:?}", outcome.measurement.quantity).to_lowercase(),
+                    value: outcome.value.as_ref().ok().copied(),
+                    error: outcome.value.as_ref().err().cloned(),
+                    min: outcome.measurement.min,
+                    max: outcome.measurement.max,
+                    passed: outcome.passed(),
+                    time: outcome.time.as_secs_f64(),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl std::convert::TryInto<crate::Command> for Command {
@@ -89,4 +442,176 @@ pub struct Ka3005p {
     /// Manually select power supply serial device
     #[clap(short, long)]
     pub device: Option<String>,
+    /// Switch the output off if no command was received within the given time (e.g. 5s)
+    /// or when the program exits
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub watchdog: Option<std::time::Duration>,
+    /// Reconnect to the power supply if the connection is lost (e.g. the supply was power cycled)
+    #[clap(long)]
+    pub reconnect: bool,
+    /// Baud rate of the serial port
+    #[clap(long, default_value_t = 9600)]
+    pub baud: u32,
+    /// How long to wait for a response of the power supply (e.g. 60ms)
+    #[clap(long, default_value = "60ms", value_parser = humantime::parse_duration)]
+    pub serial_timeout: std::time::Duration,
+    /// Parity of the serial port
+    #[clap(long, value_enum, default_value_t = Parity::None)]
+    pub parity: Parity,
+    /// Stop bits of the serial port
+    #[clap(long, value_enum, default_value_t = StopBits::One)]
+    pub stop_bits: StopBits,
+    /// Minimum time between two commands, for power supplies which drop commands sent back-to-back (e.g. 50ms)
+    #[clap(long, default_value = "0s", value_parser = humantime::parse_duration)]
+    pub command_delay: std::time::Duration,
+    /// Terminator appended to every command
+    #[clap(long, value_enum, default_value_t = Terminator::None)]
+    pub terminator: Terminator,
+    /// How often a query is repeated if the power supply didn't respond with valid data
+    #[clap(long, default_value_t = 0)]
+    pub retries: u32,
+    /// Use the quirks of this model (e.g. "72-2550") instead of detecting them via *IDN?
+    #[clap(long)]
+    pub model: Option<String>,
+    /// Limit how fast the voltage and current change while the output is on, e.g. 2V/s or 2V/s,0.5A/s
+    #[clap(long)]
+    pub slew: Option<crate::slew::SlewRate>,
+    /// Record all transfers with the power supply to a transcript file
+    #[clap(long)]
+    pub record: Option<std::path::PathBuf>,
+    /// Answer with the responses of a recorded transcript instead of a power supply
+    #[clap(long, conflicts_with = "device")]
+    pub replay: Option<std::path::PathBuf>,
+}
+
+impl Ka3005p {
+    /// Builder for the power supply configured by the command line arguments
+    pub fn builder(&self) -> crate::Ka3005pBuilder {
+        let mut builder = crate::Ka3005p::builder()
+            .baud_rate(self.baud)
+            .timeout(self.serial_timeout)
+            .parity(self.parity.into())
+            .stop_bits(self.stop_bits.into())
+            .command_delay(self.command_delay)
+            .terminator(self.terminator.as_str())
+            .retries(self.retries);
+        if let Some(device) = &self.device {
+            builder = builder.port_name(device);
+        }
+        if self.reconnect {
+            builder = builder.reconnect(crate::reconnect::Reconnect::default());
+        }
+        if let Some(model) = &self.model {
+            builder = builder.quirks(crate::quirks::Quirks::for_identity(model));
+        }
+        if let Some(slew_rate) = self.slew {
+            builder = builder.slew_rate(slew_rate);
+        }
+        builder
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, ValueEnum)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+impl From<Parity> for serialport::Parity {
+    fn from(parity: Parity) -> Self {
+        match parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, ValueEnum)]
+pub enum StopBits {
+    #[value(name = "1")]
+    One,
+    #[value(name = "2")]
+    Two,
+}
+
+impl From<StopBits> for serialport::StopBits {
+    fn from(stop_bits: StopBits) -> Self {
+        match stop_bits {
+            StopBits::One => serialport::StopBits::One,
+            StopBits::Two => serialport::StopBits::Two,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, ValueEnum)]
+pub enum Terminator {
+    None,
+    Cr,
+    Lf,
+    Crlf,
+}
+
+impl Terminator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Terminator::None => "",
+            Terminator::Cr => "\r",
+            Terminator::Lf => "\n",
+            Terminator::Crlf => "\r\n",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_check_file() {
+        let file: CheckFile = toml::from_str(
+            r#"
+            [setup]
+            voltage = 5.0
+            output = true
+
+            [[measurement]]
+            name = "quiescent current"
+            quantity = "current"
+            max = 0.02
+            settle = "500ms"
+            "#,
+        )
+        .unwrap();
+        let settings = file.settings().unwrap();
+        assert_eq!(settings.voltage, Some(5.0));
+        assert_eq!(settings.output, Some(crate::Switch::On));
+        let measurements = file.measurements().unwrap();
+        assert_eq!(measurements[0].quantity, crate::watch::Quantity::Current);
+        assert_eq!(
+            measurements[0].settle,
+            std::time::Duration::from_millis(500)
+        );
+        let file: CheckFile =
+            toml::from_str("[[measurement]]\nname = \"x\"\nquantity = \"temperature\"").unwrap();
+        assert!(file.measurements().is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("4.99"))
+            .on("ISET1?", Reply::data("1.000"))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = crate::Ka3005p::new_from_serial(Box::new(mock)).unwrap();
+        assert_eq!(Value::Voltage.read(&mut dev, false).unwrap(), "4.99");
+        assert_eq!(Value::SetCurrent.read(&mut dev, true).unwrap(), "1.000 A");
+        assert_eq!(Value::Output.read(&mut dev, true).unwrap(), "on");
+        assert_eq!(Value::Mode.read(&mut dev, false).unwrap(), "CV");
+        assert_eq!(Value::Lock.read(&mut dev, false).unwrap(), "off");
+        let error = Value::Current.read(&mut dev, false).unwrap_err();
+        assert_eq!(crate::Error::of(&error), Some(&crate::Error::Timeout));
+    }
 }
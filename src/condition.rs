@@ -0,0 +1,262 @@
+//! Conditions on the state of the power supply, e.g. to wait until the load draws less than 20mA.
+//!
+//! Conditions are written as `<name> <operator> <value>`:
+//! * `voltage`, `current` and `power` (W) can be compared with `<`, `<=`, `>` and `>=`, e.g. `current < 0.02`
+//! * `mode` (`CV`/`CC`) and `output` (`on`/`off`) can be compared with `==` and `!=`, e.g. `mode != CC`
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::condition::Condition;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let condition: Condition = "current < 0.02".parse().unwrap();
+//! match dev.wait_for(&[condition], Duration::from_secs(30), Duration::from_millis(200)).unwrap() {
+//!     Some(status) => println!("{}", status),
+//!     None => println!("Still drawing too much current"),
+//! }
+//! ```
+use crate::watch::Quantity;
+use crate::{Ka3005p, Mode, Status, Switch};
+use anyhow::{anyhow, bail};
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Operators to compare a measured value with a level
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Comparison {
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+/// A condition on the status of the power supply
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Condition {
+    /// A measured value compared with a level
+    Measured {
+        /// The measured quantity
+        quantity: Quantity,
+        /// How the value is compared
+        comparison: Comparison,
+        /// The level the value is compared with
+        level: f32,
+    },
+    /// Channel 1 is (`true`) or isn't (`false`) in this mode
+    Mode(Mode, bool),
+    /// The output is (`true`) or isn't (`false`) in this state
+    Output(Switch, bool),
+}
+
+impl Condition {
+    /// Whether the condition holds for `status`
+    pub fn holds(&self, status: &Status) -> bool {
+        match *self {
+            Condition::Measured {
+                quantity,
+                comparison,
+                level,
+            } => {
+                let value = quantity.of(status);
+                match comparison {
+                    Comparison::Less => value < level,
+                    Comparison::LessOrEqual => value <= level,
+                    Comparison::Greater => value > level,
+                    Comparison::GreaterOrEqual => value >= level,
+                }
+            }
+            Condition::Mode(mode, equal) => (status.flags.channel1 == mode) == equal,
+            Condition::Output(output, equal) => (status.flags.output == output) == equal,
+        }
+    }
+}
+
+/// Supported operators, the longest match wins so `<=` isn't parsed as `<`
+const OPERATORS: [&str; 6] = ["<=", ">=", "==", "!=", "<", ">"];
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (position, operator) = OPERATORS
+            .iter()
+            .filter_map(|operator| s.find(operator).map(|position| (position, *operator)))
+            .min_by_key(|(position, operator)| (*position, std::cmp::Reverse(operator.len())))
+            .ok_or_else(|| anyhow!("Missing operator in condition {:?}", s))?;
+        let name = s[..position].trim().to_lowercase();
+        let value = s[position + operator.len()..].trim();
+        let quantity = match name.as_str() {
+            "voltage" => Some(Quantity::Voltage),
+            "current" => Some(Quantity::Current),
+            "power" => Some(Quantity::Power),
+            _ => None,
+        };
+        if let Some(quantity) = quantity {
+            let comparison = match operator {
+                "<" => Comparison::Less,
+                "<=" => Comparison::LessOrEqual,
+                ">" => Comparison::Greater,
+                ">=" => Comparison::GreaterOrEqual,
+                _ => bail!("{} can only be compared with <, <=, > or >=", name),
+            };
+            let level = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid level {:?} in condition {:?}", value, s))?;
+            return Ok(Condition::Measured {
+                quantity,
+                comparison,
+                level,
+            });
+        }
+        let equal = match operator {
+            "==" => true,
+            "!=" => false,
+            _ => bail!("{} can only be compared with == or !=", name),
+        };
+        match name.as_str() {
+            "mode" => match value.to_uppercase().as_str() {
+                "CC" => Ok(Condition::Mode(Mode::Cc, equal)),
+                "CV" => Ok(Condition::Mode(Mode::Cv, equal)),
+                _ => bail!("Invalid mode {:?}, expected CC or CV", value),
+            },
+            "output" => Ok(Condition::Output(value.parse()?, equal)),
+            _ => bail!(
+                "Unknown value {:?}, expected voltage, current, power, mode or output",
+                name
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = |equal| if equal { "==" } else { "!=" };
+        match *self {
+            Condition::Measured {
+                quantity,
+                comparison,
+                level,
+            } => {
+                let comparison = match comparison {
+                    Comparison::Less => "<",
+                    Comparison::LessOrEqual => "<=",
+                    Comparison::Greater => ">",
+                    Comparison::GreaterOrEqual => ">=",
+                };
+                write!(f, "{:?} {} {}", quantity, comparison, level)
+            }
+            Condition::Mode(mode, equal) => write!(f, "Mode {} {:?}", operator(equal), mode),
+            Condition::Output(output, equal) => {
+                write!(f, "Output {} {:?}", operator(equal), output)
+            }
+        }
+    }
+}
+
+impl Ka3005p {
+    /// Poll the status every `interval` until all `conditions` hold.
+    ///
+    /// Returns the status which fulfilled the conditions, or `None` if they didn't hold within `timeout`
+    /// (use [`Duration::MAX`] to wait forever).
+    pub fn wait_for(
+        &mut self,
+        conditions: &[Condition],
+        timeout: Duration,
+        interval: Duration,
+    ) -> anyhow::Result<Option<Status>> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let status = self.status()?;
+            if conditions.iter().all(|condition| condition.holds(&status)) {
+                return Ok(Some(status));
+            }
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            thread::sleep(interval.min(remaining));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_parse() {
+        let condition: Condition = "current<0.02".parse().unwrap();
+        assert_eq!(
+            condition,
+            Condition::Measured {
+                quantity: Quantity::Current,
+                comparison: Comparison::Less,
+                level: 0.02
+            }
+        );
+        assert_eq!(condition.to_string(), "Current < 0.02");
+        assert_eq!(
+            "Power >= 1.5".parse::<Condition>().unwrap().to_string(),
+            "Power >= 1.5"
+        );
+        assert_eq!(
+            "mode != cc".parse::<Condition>().unwrap(),
+            Condition::Mode(Mode::Cc, false)
+        );
+        assert_eq!(
+            "output == on".parse::<Condition>().unwrap(),
+            Condition::Output(Switch::On, true)
+        );
+        for invalid in [
+            "current",
+            "current == 1",
+            "mode < CV",
+            "temperature > 3",
+            "voltage < x",
+        ] {
+            assert!(invalid.parse::<Condition>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_wait_for() {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("5.00"))
+            .once("IOUT1?", Reply::data("0.500"))
+            .on("IOUT1?", Reply::data("0.010"))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("1.000"))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock)).unwrap();
+        let conditions = [
+            "current < 0.02".parse().unwrap(),
+            "power < 0.1".parse().unwrap(),
+        ];
+        let status = dev
+            .wait_for(&conditions, Duration::from_secs(1), Duration::ZERO)
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.current, 0.01);
+        let conditions = ["mode == CC".parse().unwrap()];
+        let status = dev
+            .wait_for(
+                &conditions,
+                Duration::from_millis(20),
+                Duration::from_millis(5),
+            )
+            .unwrap();
+        assert_eq!(status, None);
+    }
+}
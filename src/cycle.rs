@@ -0,0 +1,252 @@
+//! Power cycle the output repeatedly, e.g. to test whether a board boots reliably.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::cycle::Cycling;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let cycling = Cycling {
+//!     off: Duration::from_secs(2),
+//!     on: Duration::from_secs(10),
+//!     count: Some(1000),
+//!     // A board which booted draws more than 50mA
+//!     expect: vec!["current > 0.05".parse().unwrap()],
+//! };
+//! for cycle in dev.cycle(cycling) {
+//!     let cycle = cycle.unwrap();
+//!     println!("{}", cycle);
+//!     if !cycle.passed() {
+//!         break;
+//!     }
+//! }
+//! ```
+use crate::condition::Condition;
+use crate::{Command, Ka3005p, Mode, Status, Switch};
+use anyhow::Context;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How to power cycle the output
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cycling {
+    /// How long the output is off in each cycle
+    pub off: Duration,
+    /// How long the output is on in each cycle
+    pub on: Duration,
+    /// Number of cycles, `None` cycles until the iteration is stopped
+    pub count: Option<u32>,
+    /// Conditions which have to hold at the end of each on phase
+    pub expect: Vec<Condition>,
+}
+
+/// Measurements of a single power cycle
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cycle {
+    /// Number of the cycle, starting at 1
+    pub number: u32,
+    /// Highest current measured during the on phase, e.g. the inrush current
+    pub peak_current: f32,
+    /// The power supply was in CC mode at some point of the on phase
+    pub current_limited: bool,
+    /// Status at the end of the on phase
+    pub status: Status,
+    /// Expected conditions which didn't hold at the end of the on phase
+    pub failed: Vec<Condition>,
+}
+
+impl Cycle {
+    /// Whether all expected conditions held
+    pub fn passed(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cycle {}: Peak: {:.3}{}, Voltage: {:.2}, Current: {:.3}, Mode: {:?}, ",
+            self.number,
+            self.peak_current,
+            if self.current_limited { " (CC)" } else { "" },
+            self.status.voltage,
+            self.status.current,
+            self.status.flags.channel1
+        )?;
+        if self.passed() {
+            return write!(f, "PASS");
+        }
+        let failed: Vec<String> = self.failed.iter().map(|c| c.to_string()).collect();
+        write!(f, "FAIL ({} didn't hold)", failed.join(", "))
+    }
+}
+
+/// A step of a cycle, see [`Cycling::run`]
+pub type Step<'a> = &'a mut dyn FnMut(&mut Ka3005p) -> anyhow::Result<()>;
+
+/// Iterator over the cycles, see [`Ka3005p::cycle`].
+pub struct Cycles<'a> {
+    dev: &'a mut Ka3005p,
+    cycling: Cycling,
+    number: u32,
+    failed: bool,
+}
+
+impl Iterator for Cycles<'_> {
+    type Item = anyhow::Result<Cycle>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cycling.count.is_some_and(|count| self.number >= count) {
+            return None;
+        }
+        self.number += 1;
+        let cycle = self.cycling.run(self.number, |wait, step| {
+            thread::sleep(wait);
+            step(self.dev)
+        });
+        // Stop after an error, the state of the output is unknown
+        self.failed = cycle.is_err();
+        Some(cycle)
+    }
+}
+
+impl Cycling {
+    /// Run a single cycle, e.g. to release the power supply between the steps. See [`Ka3005p::cycle`].
+    ///
+    /// `step` first waits for the given time without holding the power supply, e.g. to renew a
+    /// watchdog meanwhile, and then runs the given step on it.
+    pub fn run(
+        &self,
+        number: u32,
+        mut step: impl FnMut(Duration, Step<'_>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<Cycle> {
+        step(Duration::ZERO, &mut |dev| {
+            dev.execute(Command::Power(Switch::Off))
+        })?;
+        step(self.off, &mut |dev| dev.execute(Command::Power(Switch::On)))?;
+        let start = Instant::now();
+        let mut peak_current = 0f32;
+        let mut current_limited = false;
+        // Sample as fast as the power supply answers, at least once
+        loop {
+            step(Duration::ZERO, &mut |dev| {
+                peak_current = peak_current.max(dev.read_measured_current()?);
+                current_limited |= dev.read_flags()?.channel1 == Mode::Cc;
+                Ok(())
+            })?;
+            if start.elapsed() >= self.on {
+                break;
+            }
+        }
+        let mut status = None;
+        step(Duration::ZERO, &mut |dev| {
+            status = Some(dev.status()?);
+            Ok(())
+        })?;
+        let status = status.context("the status wasn't read")?;
+        let failed = self
+            .expect
+            .iter()
+            .filter(|condition| !condition.holds(&status))
+            .copied()
+            .collect();
+        Ok(Cycle {
+            number,
+            peak_current: peak_current.max(status.current),
+            current_limited: current_limited || status.flags.channel1 == Mode::Cc,
+            status,
+            failed,
+        })
+    }
+}
+
+impl Ka3005p {
+    /// Switch the output off for `cycling.off` and on for `cycling.on`, repeatedly.
+    ///
+    /// During the on phase the current and mode are sampled as fast as the power supply answers,
+    /// so short inrush peaks may be missed. The output stays on after the last cycle.
+    /// The iteration stops after the first error.
+    pub fn cycle(&mut self, cycling: Cycling) -> Cycles<'_> {
+        Cycles {
+            dev: self,
+            cycling,
+            number: 0,
+            failed: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_cycle() {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("5.00"))
+            .once("IOUT1?", Reply::data("0.800"))
+            .on("IOUT1?", Reply::data("0.100"))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("1.000"))
+            .once("STATUS?", Reply::Data(vec![0x50]))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let cycling = Cycling {
+            off: Duration::ZERO,
+            on: Duration::ZERO,
+            count: Some(2),
+            expect: vec![
+                "current > 0.05".parse().unwrap(),
+                "mode == CC".parse().unwrap(),
+            ],
+        };
+        let cycles: Vec<Cycle> = dev.cycle(cycling).collect::<anyhow::Result<_>>().unwrap();
+        assert_eq!(cycles.len(), 2);
+        assert_eq!(cycles[0].peak_current, 0.8);
+        assert!(cycles[0].current_limited);
+        assert_eq!(cycles[1].peak_current, 0.1);
+        assert!(!cycles[1].current_limited);
+        assert_eq!(cycles[1].failed, vec!["mode == CC".parse().unwrap()]);
+        assert_eq!(
+            cycles[1].to_string(),
+            "Cycle 2: Peak: 0.100, Voltage: 5.00, Current: 0.100, Mode: Cv, FAIL (Mode == Cc didn't hold)"
+        );
+        let requests = mock.requests();
+        assert_eq!(requests[..2], ["OUT0", "OUT1"]);
+        assert_eq!(requests.iter().filter(|r| *r == "OUT1").count(), 2);
+    }
+
+    #[test]
+    fn test_run_waits_between_steps() {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("5.00"))
+            .on("IOUT1?", Reply::data("0.100"))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("1.000"))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let cycling = Cycling {
+            off: Duration::from_secs(2),
+            on: Duration::ZERO,
+            count: None,
+            expect: vec![],
+        };
+        let mut waits = vec![];
+        let cycle = cycling
+            .run(7, |wait, step| {
+                waits.push(wait);
+                step(&mut dev)
+            })
+            .unwrap();
+        assert_eq!(cycle.number, 7);
+        assert!(cycle.passed());
+        // The off phase is waited for without holding the power supply
+        assert_eq!(
+            waits,
+            [Duration::ZERO, cycling.off, Duration::ZERO, Duration::ZERO]
+        );
+        assert_eq!(mock.requests()[..2], ["OUT0", "OUT1"]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Failures which callers may want to handle specifically, e.g. to retry after a timeout.
+//!
+//! The library returns [`anyhow::Error`]s, use [`Error::of`] to find out whether one of them was caused by an [`Error`].
+//!
+//! ```no_run
+//! use ka3005p::Error;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! match dev.read_set_voltage() {
+//!     Ok(voltage) => println!("{}", voltage),
+//!     Err(e) if Error::of(&e) == Some(&Error::Timeout) => println!("No response, try again"),
+//!     Err(e) => panic!("{:#}", e),
+//! }
+//! ```
+use std::fmt;
+
+/// Typed causes of the errors returned by the library
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum Error {
+    /// No (compatible) power supply was found
+    DeviceNotFound(String),
+    /// The power supply didn't respond
+    Timeout,
+    /// The power supply responded with data which couldn't be decoded
+    Protocol(String),
+    /// A value is outside of the range supported by the power supply
+    LimitExceeded(String),
+}
+
+impl Error {
+    /// Find the typed cause of an error returned by the library, if there is one.
+    pub fn of(error: &anyhow::Error) -> Option<&Error> {
+        error.chain().find_map(|cause| cause.downcast_ref())
+    }
+
+    /// Like [`Error::of`], but also recognizes timeouts and missing devices reported by the serial port.
+    pub fn classify(error: &anyhow::Error) -> Option<Error> {
+        if let Some(cause) = Error::of(error) {
+            return Some(cause.clone());
+        }
+        let kind = error.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+                return Some(e.kind());
+            }
+            cause
+                .downcast_ref::<serialport::Error>()
+                .map(|e| match e.kind() {
+                    serialport::ErrorKind::NoDevice => std::io::ErrorKind::NotFound,
+                    serialport::ErrorKind::Io(kind) => kind,
+                    _ => std::io::ErrorKind::Other,
+                })
+        });
+        match kind {
+            Some(std::io::ErrorKind::TimedOut) => Some(Error::Timeout),
+            Some(std::io::ErrorKind::NotFound) => Some(Error::DeviceNotFound(error.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound(message)
+            | Error::Protocol(message)
+            | Error::LimitExceeded(message) => write!(f, "{}", message),
+            Error::Timeout => write!(f, "PSU did not respond with data"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_of() {
+        let error = Err::<(), _>(Error::Timeout)
+            .context("Could not read the voltage")
+            .unwrap_err();
+        assert_eq!(Error::of(&error), Some(&Error::Timeout));
+        assert_eq!(Error::of(&anyhow::anyhow!("Other error")), None);
+        let error = crate::MemorySlot::new(6).unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_classify() {
+        let error = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::TimedOut))
+            .context("Could not read the voltage");
+        assert_eq!(Error::classify(&error), Some(Error::Timeout));
+        let error = anyhow::Error::new(serialport::Error::new(
+            serialport::ErrorKind::NoDevice,
+            "No such device",
+        ));
+        assert!(matches!(
+            Error::classify(&error),
+            Some(Error::DeviceNotFound(_))
+        ));
+        assert_eq!(Error::classify(&anyhow::anyhow!("Other error")), None);
+    }
+
+    #[test]
+    fn test_device_errors() {
+        let mock = crate::mock::MockPort::new();
+        mock.on("VSET1?", crate::mock::Reply::data("?"));
+        let mut dev = crate::Ka3005p::new_from_serial(Box::new(mock)).unwrap();
+        let error = dev.read_set_current().unwrap_err();
+        assert_eq!(Error::of(&error), Some(&Error::Timeout));
+        let error = dev.read_set_voltage().unwrap_err();
+        assert!(matches!(Error::of(&error), Some(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,426 @@
+//! C interface of the library, available with the `ffi` feature.
+//!
+//! The header `include/ka3005p.h` is generated from this module with `just header`.
+//! All functions return `KA3005P_RESULT_OK` (0) on success and a negative error code on failure,
+//! [`ka3005p_last_error`] describes the failure.
+//!
+//! ```c
+//! #include "ka3005p.h"
+//!
+//! Ka3005p *psu = NULL;
+//! if (ka3005p_open(NULL, &psu) != KA3005P_RESULT_OK) {
+//!     fprintf(stderr, "%s\n", ka3005p_last_error());
+//!     return 1;
+//! }
+//! ka3005p_set_voltage(psu, 5.0f);
+//! ka3005p_set_output(psu, true);
+//!
+//! Ka3005pStatus status;
+//! if (ka3005p_read_status(psu, &status) == KA3005P_RESULT_OK) {
+//!     printf("%.2f V %.3f A\n", status.voltage, status.current);
+//! }
+//! ka3005p_close(psu);
+//! ```
+use crate::{Command, Ka3005p, Switch};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Result of a call, negative values are errors
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Ka3005pResult {
+    /// The call succeeded
+    Ok = 0,
+    /// Any error without a more specific code, e.g. an I/O error
+    Error = -1,
+    /// No (compatible) power supply was found
+    DeviceNotFound = -2,
+    /// The power supply didn't respond
+    Timeout = -3,
+    /// The power supply responded with data which couldn't be decoded
+    Protocol = -4,
+    /// A value is outside of the range supported by the power supply
+    LimitExceeded = -5,
+    /// A pointer was NULL or a string wasn't valid UTF-8
+    InvalidArgument = -6,
+    /// The buffer is too small for the result, the required size was stored
+    BufferTooSmall = -7,
+}
+
+/// CC or CV mode
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Ka3005pMode {
+    /// Constant current
+    Cc = 0,
+    /// Constant voltage
+    Cv = 1,
+}
+
+impl From<crate::Mode> for Ka3005pMode {
+    fn from(mode: crate::Mode) -> Self {
+        match mode {
+            crate::Mode::Cc => Ka3005pMode::Cc,
+            crate::Mode::Cv => Ka3005pMode::Cv,
+        }
+    }
+}
+
+/// Measurements, settings and flags of the power supply
+#[repr(C)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Ka3005pStatus {
+    /// Measured voltage in volts
+    pub voltage: f32,
+    /// Measured current in amps
+    pub current: f32,
+    /// Target voltage in volts
+    pub set_voltage: f32,
+    /// Current limit in amps
+    pub set_current: f32,
+    /// Output enabled
+    pub output: bool,
+    /// Mode of channel 1
+    pub mode: Ka3005pMode,
+    /// Interface beep enabled
+    pub beep: bool,
+    /// Physical buttons locked
+    pub locked: bool,
+}
+
+impl From<crate::Status> for Ka3005pStatus {
+    fn from(status: crate::Status) -> Self {
+        Ka3005pStatus {
+            voltage: status.voltage,
+            current: status.current,
+            set_voltage: status.set_voltage,
+            set_current: status.set_current,
+            output: status.flags.output.into(),
+            mode: status.flags.channel1.into(),
+            beep: status.flags.beep.into(),
+            locked: status.flags.lock == crate::Lock::Locked,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+struct Failure {
+    code: Ka3005pResult,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: Ka3005pResult, message: impl Into<String>) -> Self {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(error: anyhow::Error) -> Self {
+        let code = match crate::Error::classify(&error) {
+            Some(crate::Error::DeviceNotFound(_)) => Ka3005pResult::DeviceNotFound,
+            Some(crate::Error::Timeout) => Ka3005pResult::Timeout,
+            Some(crate::Error::Protocol(_)) => Ka3005pResult::Protocol,
+            Some(crate::Error::LimitExceeded(_)) => Ka3005pResult::LimitExceeded,
+            None => Ka3005pResult::Error,
+        };
+        Failure::new(code, format!("{:#}", error))
+    }
+}
+
+/// Run a call, remember its error message and make sure no panic unwinds into C.
+fn call(f: impl FnOnce() -> Result<(), Failure>) -> Ka3005pResult {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(Failure::new(
+            Ka3005pResult::Error,
+            "Panic in the ka3005p library",
+        ))
+    });
+    match result {
+        Ok(()) => Ka3005pResult::Ok,
+        Err(failure) => {
+            let message = CString::new(failure.message.replace('\0', ""))
+                .expect("NUL bytes have been removed");
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            failure.code
+        }
+    }
+}
+
+/// # Safety
+///
+/// `device` must be NULL or a handle returned by [`ka3005p_open`] which wasn't closed yet.
+unsafe fn device<'a>(device: *mut Ka3005p) -> Result<&'a mut Ka3005p, Failure> {
+    device
+        .as_mut()
+        .ok_or_else(|| Failure::new(Ka3005pResult::InvalidArgument, "The device is NULL"))
+}
+
+/// Copy `text` as NUL terminated string into `buffer`.
+///
+/// # Safety
+///
+/// `buffer` must be NULL or valid for `*size` bytes, `size` must be NULL or valid.
+unsafe fn copy_out(text: &str, buffer: *mut c_char, size: *mut usize) -> Result<(), Failure> {
+    let size = size
+        .as_mut()
+        .ok_or_else(|| Failure::new(Ka3005pResult::InvalidArgument, "The size is NULL"))?;
+    let required = text.len() + 1;
+    if buffer.is_null() || *size < required {
+        *size = required;
+        return Err(Failure::new(
+            Ka3005pResult::BufferTooSmall,
+            format!("The buffer is too small, {} bytes are required", required),
+        ));
+    }
+    std::ptr::copy_nonoverlapping(text.as_ptr().cast::<c_char>(), buffer, text.len());
+    *buffer.add(text.len()) = 0;
+    *size = required;
+    Ok(())
+}
+
+/// Message of the last failed call on this thread, or NULL if no call failed yet.
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn ka3005p_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Store the names of the serial ports with a power supply in `buffer`, separated by newlines.
+///
+/// `size` has to contain the size of `buffer` and receives the number of bytes used (including
+/// the terminating NUL). If `buffer` is NULL or too small, the required size is stored and
+/// `KA3005P_RESULT_BUFFER_TOO_SMALL` is returned.
+///
+/// # Safety
+///
+/// `buffer` must be NULL or valid for `*size` bytes, `size` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_list_ports(
+    buffer: *mut c_char,
+    size: *mut usize,
+) -> Ka3005pResult {
+    call(|| {
+        let names: Vec<String> = crate::list_serial_ports()
+            .into_iter()
+            .map(|info| info.port_name)
+            .collect();
+        copy_out(&names.join("\n"), buffer, size)
+    })
+}
+
+/// Open the power supply at `port_name`, or the first one found if `port_name` is NULL.
+///
+/// On success the handle is stored in `device`, it has to be released with [`ka3005p_close`].
+///
+/// # Safety
+///
+/// `port_name` must be NULL or a NUL terminated string, `device` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_open(
+    port_name: *const c_char,
+    device: *mut *mut Ka3005p,
+) -> Ka3005pResult {
+    call(|| {
+        let device = device
+            .as_mut()
+            .ok_or_else(|| Failure::new(Ka3005pResult::InvalidArgument, "The device is NULL"))?;
+        let mut builder = Ka3005p::builder();
+        if !port_name.is_null() {
+            let port_name = CStr::from_ptr(port_name).to_str().map_err(|_| {
+                Failure::new(
+                    Ka3005pResult::InvalidArgument,
+                    "The port name isn't valid UTF-8",
+                )
+            })?;
+            builder = builder.port_name(port_name);
+        }
+        *device = Box::into_raw(Box::new(builder.open()?));
+        Ok(())
+    })
+}
+
+/// Close the connection to the power supply, NULL is ignored.
+///
+/// # Safety
+///
+/// `device` must be NULL or a handle returned by [`ka3005p_open`] which wasn't closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_close(device: *mut Ka3005p) {
+    if !device.is_null() {
+        call(|| {
+            drop(Box::from_raw(device));
+            Ok(())
+        });
+    }
+}
+
+/// Store the identification string of the power supply in `buffer`, see [`ka3005p_list_ports`] for `size`.
+///
+/// # Safety
+///
+/// `device` must be a handle returned by [`ka3005p_open`], `buffer` must be NULL or valid for
+/// `*size` bytes and `size` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_identify(
+    device: *mut Ka3005p,
+    buffer: *mut c_char,
+    size: *mut usize,
+) -> Ka3005pResult {
+    call(|| {
+        let identity = self::device(device)?.identify()?;
+        copy_out(&identity, buffer, size)
+    })
+}
+
+/// Set the target voltage in volts.
+///
+/// # Safety
+///
+/// `device` must be a handle returned by [`ka3005p_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_set_voltage(device: *mut Ka3005p, voltage: f32) -> Ka3005pResult {
+    call(|| Ok(self::device(device)?.execute(Command::Voltage(voltage))?))
+}
+
+/// Set the current limit in amps.
+///
+/// # Safety
+///
+/// `device` must be a handle returned by [`ka3005p_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_set_current(device: *mut Ka3005p, current: f32) -> Ka3005pResult {
+    call(|| Ok(self::device(device)?.execute(Command::Current(current))?))
+}
+
+/// Switch the output on or off.
+///
+/// # Safety
+///
+/// `device` must be a handle returned by [`ka3005p_open`].
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_set_output(device: *mut Ka3005p, enable: bool) -> Ka3005pResult {
+    call(|| Ok(self::device(device)?.execute(Command::Power(Switch::from(enable)))?))
+}
+
+/// Read the measurements, settings and flags of the power supply into `status`.
+///
+/// # Safety
+///
+/// `device` must be a handle returned by [`ka3005p_open`], `status` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn ka3005p_read_status(
+    device: *mut Ka3005p,
+    status: *mut Ka3005pStatus,
+) -> Ka3005pResult {
+    call(|| {
+        let status = status
+            .as_mut()
+            .ok_or_else(|| Failure::new(Ka3005pResult::InvalidArgument, "The status is NULL"))?;
+        *status = self::device(device)?.status()?.into();
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+    use std::ptr;
+
+    fn open(mock: &MockPort) -> *mut Ka3005p {
+        Box::into_raw(Box::new(
+            Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap(),
+        ))
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(ka3005p_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_status_and_commands() {
+        let mock = MockPort::new();
+        mock.on("STATUS?", Reply::Data(vec![0x51]))
+            .on("VOUT1?", Reply::data("4.99"))
+            .on("IOUT1?", Reply::data("0.100"))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("1.000"));
+        let dev = open(&mock);
+        let mut status = Ka3005pStatus {
+            voltage: 0.0,
+            current: 0.0,
+            set_voltage: 0.0,
+            set_current: 0.0,
+            output: false,
+            mode: Ka3005pMode::Cc,
+            beep: false,
+            locked: true,
+        };
+        unsafe {
+            assert_eq!(ka3005p_set_voltage(dev, 5.0), Ka3005pResult::Ok);
+            assert_eq!(ka3005p_set_output(dev, true), Ka3005pResult::Ok);
+            assert_eq!(ka3005p_read_status(dev, &mut status), Ka3005pResult::Ok);
+            ka3005p_close(dev);
+        }
+        assert_eq!(status.voltage, 4.99);
+        assert_eq!(status.set_current, 1.0);
+        assert_eq!(status.mode, Ka3005pMode::Cv);
+        assert!(status.output && status.beep && !status.locked);
+        assert_eq!(&mock.requests()[..2], ["VSET1:5.00", "OUT1"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mock = MockPort::new();
+        mock.on("*IDN?", Reply::data("KORAD KA3005P V5.8"));
+        let dev = open(&mock);
+        unsafe {
+            assert_eq!(
+                ka3005p_read_status(dev, ptr::null_mut()),
+                Ka3005pResult::InvalidArgument
+            );
+            assert_eq!(last_error(), "The status is NULL");
+            let mut size = 4;
+            let mut buffer = [0 as c_char; 32];
+            assert_eq!(
+                ka3005p_identify(dev, buffer.as_mut_ptr(), &mut size),
+                Ka3005pResult::BufferTooSmall
+            );
+            assert_eq!(size, 19);
+            size = buffer.len();
+            assert_eq!(
+                ka3005p_identify(dev, buffer.as_mut_ptr(), &mut size),
+                Ka3005pResult::Ok
+            );
+            assert_eq!(
+                CStr::from_ptr(buffer.as_ptr()).to_str(),
+                Ok("KORAD KA3005P V5.8")
+            );
+            mock.on("*IDN?", Reply::Timeout);
+            assert_eq!(
+                ka3005p_identify(dev, buffer.as_mut_ptr(), &mut size),
+                Ka3005pResult::Timeout
+            );
+            assert_eq!(
+                ka3005p_set_voltage(ptr::null_mut(), 1.0),
+                Ka3005pResult::InvalidArgument
+            );
+            ka3005p_close(dev);
+        }
+    }
+}
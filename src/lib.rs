@@ -27,23 +27,62 @@
 //! > ka3005p voltage 12.1
 //! ```
 
+//! Enable the `serde` feature to serialize and deserialize the public types,
+//! e.g. to persist [`Status`] snapshots or load lists of [`Command`]s from JSON/TOML.
+//!
+//! Enable the `test-utils` feature for `mock::MockPort`, a scriptable serial port to unit test
+//! code using the library without a power supply.
+//!
+//! Enable the `ffi` feature to export a C interface from the shared library, see `ffi`.
+//!
+//! The default `cli` feature builds the command line utility, library users can disable it
+//! via `default-features = false` to skip its dependencies.
+
 #![deny(warnings)]
 #![warn(missing_docs)]
 use anyhow::Context;
-use log::debug;
+use log::{debug, error, info, warn};
 use std::fmt;
 use std::io;
 use std::str;
+use std::thread;
 use std::time;
 
+pub mod acquisition;
+mod builder;
+pub mod check;
+pub mod condition;
+pub mod cycle;
+pub use builder::Ka3005pBuilder;
+mod error;
+pub use error::Error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "cli")]
 #[doc(hidden)] // Users of the library shouldn't use this
 pub mod cli;
 pub use serialport;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod mock;
+pub mod presets;
+pub mod protocol;
+pub use protocol::{Query, Response};
+pub mod proxy;
 #[cfg(feature = "python_module")]
 pub mod py_module;
+pub mod quirks;
+pub mod reconnect;
+pub mod settings;
+pub mod slew;
+pub mod transcript;
+mod virtual_port;
+pub mod watch;
+pub mod watchdog;
 
 /// On / Off
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Switch {
     /// Enable the feature/output
     On,
@@ -71,7 +110,8 @@ impl std::convert::From<bool> for Switch {
 }
 
 /// Commands supported by the power supply.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Command {
     /// Enable/Disable Power
     Power(Switch),
@@ -81,18 +121,86 @@ pub enum Command {
     Ovp(Switch),
     /// Enable/Disable over current protection
     Ocp(Switch),
-    /// Store current settings to memory. Supports 1 to 5
-    Save(u32),
+    /// Store current settings to memory.
+    Save(MemorySlot),
     /// Load stored setting. Note will disable power supply output on load.
-    Load(u32),
+    Load(MemorySlot),
     /// Sets the voltage. Units in Volts
     Voltage(f32),
     /// Sets the current. Units in Amps
     Current(f32),
 }
 
+/// A memory slot of the power supply (M1 - M5)
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
+pub struct MemorySlot(u8);
+
+impl MemorySlot {
+    /// Highest memory slot supported by any model
+    pub const MAX: u8 = 5;
+
+    /// Create a memory slot, fails if `slot` isn't within 1 and [`MemorySlot::MAX`].
+    pub fn new(slot: u8) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            (1..=Self::MAX).contains(&slot),
+            Error::LimitExceeded(format!(
+                "Memory slot must be within 1 and {}, got {}",
+                Self::MAX,
+                slot
+            ))
+        );
+        Ok(MemorySlot(slot))
+    }
+
+    /// The number of the memory slot
+    pub fn get(self) -> u8 {
+        self.0
+    }
+
+    /// All memory slots up to and including `last`
+    pub fn up_to(last: u8) -> impl Iterator<Item = MemorySlot> {
+        (1..=last.min(Self::MAX)).map(MemorySlot)
+    }
+}
+
+impl std::convert::TryFrom<u8> for MemorySlot {
+    type Error = anyhow::Error;
+    fn try_from(slot: u8) -> Result<Self, Self::Error> {
+        Self::new(slot)
+    }
+}
+
+impl From<MemorySlot> for u8 {
+    fn from(slot: MemorySlot) -> u8 {
+        slot.0
+    }
+}
+
+impl std::convert::TryFrom<u32> for MemorySlot {
+    type Error = anyhow::Error;
+    fn try_from(slot: u32) -> Result<Self, Self::Error> {
+        Self::new(u8::try_from(slot).unwrap_or(u8::MAX))
+    }
+}
+
+impl std::str::FromStr for MemorySlot {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.parse()?)
+    }
+}
+
+impl fmt::Display for MemorySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Structure containing all the information fields from the power supply
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Flags {
     /// The raw byte
     flags: u8,
@@ -111,7 +219,8 @@ pub struct Flags {
 }
 
 /// Channel One / Two
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Channel {
     /// Channel One of the power supply
     One,
@@ -120,7 +229,8 @@ pub enum Channel {
 }
 
 /// Locked / Unlocked
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Lock {
     /// Device is currently locked. Ignores physical buttons but will still respond to serial commands
     Locked,
@@ -129,7 +239,8 @@ pub enum Lock {
 }
 
 /// CC or CV mode
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mode {
     /// Power supply is in Constant Current mode
     Cc,
@@ -138,6 +249,8 @@ pub enum Mode {
 }
 
 /// Contains the current Voltage, Current and Flags of the power supply
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Status {
     /// Flags as reported by the power supply
     pub flags: Flags,
@@ -187,39 +300,7 @@ impl From<u8> for Flags {
 }
 impl Flags {
     fn new(flags: u8) -> Self {
-        let channel1 = if flags & 0x01 != 0 {
-            Mode::Cv
-        } else {
-            Mode::Cc
-        };
-        let channel2 = if flags & 0x02 != 0 {
-            Mode::Cv
-        } else {
-            Mode::Cc
-        };
-        let beep = if flags & 0x10 != 0 {
-            Switch::On
-        } else {
-            Switch::Off
-        };
-        let lock = if flags & 0x20 != 0 {
-            Lock::Locked
-        } else {
-            Lock::Unlocked
-        };
-        let output = if flags & 0x40 != 0 {
-            Switch::On
-        } else {
-            Switch::Off
-        };
-        Flags {
-            flags,
-            channel1,
-            channel2,
-            beep,
-            lock,
-            output,
-        }
+        quirks::StatusBits::default().decode(flags)
     }
 }
 
@@ -238,80 +319,165 @@ pub fn list_serial_ports() -> Vec<serialport::SerialPortInfo> {
 
 /// Helper function that automatically finds and connects to a power supply.
 pub fn find_serial_port() -> anyhow::Result<Ka3005p> {
-    let serial_devices = list_serial_ports();
-
-    match serial_devices.len() {
-        0 => Err(anyhow::anyhow!("No Power Supply Found!")),
-        _ => Ka3005p::new(&serial_devices[0].port_name),
-    }
+    Ka3005p::builder().open()
 }
 
 impl std::convert::From<Command> for String {
     fn from(c: Command) -> Self {
-        match c {
-            Command::Power(s) => match s {
-                Switch::On => String::from("OUT1"),
-                Switch::Off => String::from("OUT0"),
-            },
-            Command::Ovp(s) => match s {
-                Switch::On => String::from("OVP1"),
-                Switch::Off => String::from("OVP0"),
-            },
-            Command::Ocp(s) => match s {
-                Switch::On => String::from("OCP1"),
-                Switch::Off => String::from("OCP0"),
-            },
-            Command::Beep(s) => match s {
-                Switch::On => String::from("BEEP1"),
-                Switch::Off => String::from("BEEP0"),
-            },
-            Command::Save(id) => format!("SAV{}", id),
-            Command::Load(id) => format!("RCL{}", id),
-            Command::Voltage(v) => format!("VSET1:{:.2}", v),
-            Command::Current(i) => format!("ISET1:{:.3}", i),
-        }
+        quirks::Quirks::default().format(c)
     }
 }
 
 /// The power supply. The main object of the library.
 pub struct Ka3005p {
     serial: Box<dyn serialport::SerialPort>,
+    port_name: Option<String>,
+    identity: Option<serialport::UsbPortInfo>,
+    reconnect: Option<reconnect::Reconnect>,
+    reconnects: u32,
+    settings: builder::Settings,
+    quirks: quirks::Quirks,
+    last_transfer: Option<time::Instant>,
+    voltage_setpoint: Option<f32>,
+    current_setpoint: Option<f32>,
+    safe_state: Vec<Command>,
+    recorder: Option<transcript::Recorder>,
+    slew_rate: Option<slew::SlewRate>,
+    /// Called between the steps of a ramp, e.g. by a [`watchdog::Watchdog`] to renew its heartbeat
+    heartbeat: Option<Box<dyn Fn() + Send>>,
 }
 
 impl Ka3005p {
     /// Create a power supply object from a serial port address.
     pub fn new(port_name: &str) -> anyhow::Result<Self> {
-        let serial = serialport::new(port_name, 9600)
-            .timeout(time::Duration::from_millis(60))
-            .parity(serialport::Parity::None)
-            .stop_bits(serialport::StopBits::One)
-            .open()?;
+        Self::builder().port_name(port_name).open()
+    }
 
-        Ok(Ka3005p { serial })
+    /// Configure the connection settings (baud rate, timeouts, pacing, ...) before opening the power supply.
+    pub fn builder() -> Ka3005pBuilder {
+        Ka3005pBuilder::default()
     }
 
     /// A convenience function to use if your power supply happens to be picky with the settings.
     /// Note the library defaults have fairly large margins so this should be unnecessary.
     pub fn new_from_serial(serial: Box<dyn serialport::SerialPort>) -> anyhow::Result<Self> {
-        Ok(Ka3005p { serial })
+        Ok(Self::with_serial(serial, builder::Settings::default()))
+    }
+
+    fn with_serial(serial: Box<dyn serialport::SerialPort>, settings: builder::Settings) -> Self {
+        Ka3005p {
+            serial,
+            port_name: None,
+            identity: None,
+            reconnect: None,
+            reconnects: 0,
+            settings,
+            quirks: quirks::Quirks::default(),
+            last_transfer: None,
+            voltage_setpoint: None,
+            current_setpoint: None,
+            safe_state: Vec::new(),
+            recorder: None,
+            slew_rate: None,
+            heartbeat: None,
+        }
+    }
+
+    /// Enable or disable automatic reconnects. If the connection to the power supply is lost
+    /// (e.g. the supply was power cycled) the device is searched again, reopened and the failed
+    /// command is retried. Only supported for power supplies opened via [`Ka3005p::new`] or the [`Ka3005pBuilder`].
+    pub fn set_reconnect(&mut self, reconnect: Option<reconnect::Reconnect>) {
+        self.reconnect = reconnect;
+    }
+
+    /// Number of times the connection to the power supply has been reestablished.
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Query the identification of the power supply, e.g. `KORAD KA3005P V5.8 SN:03379314`.
+    pub fn identify(&mut self) -> anyhow::Result<String> {
+        self.with_retries(|dev| {
+            let response = dev.run_command_response(&String::from(Query::Identity))?;
+            Ok(dev.quirks.response_text(&response).trim().to_string())
+        })
+    }
+
+    /// The quirks used to talk to this power supply.
+    pub fn quirks(&self) -> &quirks::Quirks {
+        &self.quirks
+    }
+
+    /// Override the quirks used to talk to this power supply.
+    pub fn set_quirks(&mut self, quirks: quirks::Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Configure commands which will be sent to the power supply when this object is dropped,
+    /// e.g. `vec![Command::Power(Switch::Off)]` to make sure the output is switched off
+    /// even if the program panics. Errors while applying the safe state are only logged.
+    pub fn set_safe_state(&mut self, commands: Vec<Command>) {
+        self.safe_state = commands;
+    }
+
+    /// Record every transfer with the power supply to a transcript, which can be replayed
+    /// with [`transcript::ReplayPort`]. Errors while recording are only logged.
+    pub fn set_recorder(&mut self, recorder: Option<transcript::Recorder>) {
+        self.recorder = recorder;
     }
 
-    /// Execute a command on the power supply.
+    /// Limit how fast the voltage and current change. While the output is on, setpoint changes are
+    /// broken into intermediate steps, and switching the output on starts at 0 and ramps up to the setpoints.
+    /// [`Command::Load`] is not ramped. If a ramp is interrupted by an error, the setpoint stays at the last step.
+    pub fn set_slew_rate(&mut self, slew_rate: Option<slew::SlewRate>) {
+        self.slew_rate = slew_rate;
+    }
+
+    /// Execute a command on the power supply, see [`Ka3005p::set_slew_rate`] for ramping the setpoints.
     /// Note that these supplies do not return anything on a command so the result only indicates if the serial transfer was successful.
     /// You will need to check that status to make sure the power supply is now in the state you expect.
     pub fn execute(&mut self, command: Command) -> anyhow::Result<()> {
-        self.run_command(&String::from(command))?;
+        if let Command::Save(slot) | Command::Load(slot) = command {
+            anyhow::ensure!(
+                slot.get() <= self.quirks.memory_slots,
+                Error::LimitExceeded(format!(
+                    "This power supply only supports the memory slots 1 to {}",
+                    self.quirks.memory_slots
+                ))
+            );
+        }
+        match self.slew_rate {
+            Some(slew_rate) => self.slew(slew_rate, command),
+            None => self.send(command),
+        }
+    }
+
+    /// Send a command right away and track the setpoints it changes.
+    fn send(&mut self, command: Command) -> anyhow::Result<()> {
+        self.run_command(&self.quirks.format(command))?;
+        match command {
+            Command::Voltage(v) => self.voltage_setpoint = Some(v),
+            Command::Current(i) => self.current_setpoint = Some(i),
+            Command::Load(_) => {
+                self.voltage_setpoint = None;
+                self.current_setpoint = None;
+            }
+            _ => {}
+        }
         Ok(())
     }
 
     /// Retrieve status information from the power supply
     /// Returns a struct containing all the information about the power supply
+    ///
+    /// Note: This issues five queries, use [`Ka3005p::query`] or one of the `read_*` functions
+    /// if only some of the values are needed.
     pub fn status(&mut self) -> anyhow::Result<Status> {
-        let flags: Flags = self.run_command_response("STATUS?")?[0].into();
-        let voltage = Self::printable_ascii(self.run_command_response("VOUT1?")?).parse()?;
-        let current = Self::printable_ascii(self.run_command_response("IOUT1?")?).parse()?;
-        let set_voltage = Self::printable_ascii(self.run_command_response("VSET1?")?).parse()?;
-        let set_current = Self::printable_ascii(self.run_command_response("ISET1?")?).parse()?;
+        let flags = self.read_flags()?;
+        let voltage = self.read_measured_voltage()?;
+        let current = self.read_measured_current()?;
+        let set_voltage = self.read_set_voltage()?;
+        let set_current = self.read_set_current()?;
         Ok(Status {
             flags,
             voltage,
@@ -321,59 +487,177 @@ impl Ka3005p {
         })
     }
 
+    /// Run a single query on the power supply.
+    ///
+    /// ```no_run
+    /// use ka3005p::{Query, Response};
+    ///
+    /// let mut dev = ka3005p::find_serial_port().unwrap();
+    /// if let Response::Current(current) = dev.query(Query::Current).unwrap() {
+    ///     println!("Current: {}", current);
+    /// }
+    /// ```
+    pub fn query(&mut self, query: Query) -> anyhow::Result<Response> {
+        self.with_retries(|dev| {
+            let response = dev.run_command_response(&String::from(query))?;
+            Response::parse(query, &response, &dev.quirks)
+        })
+    }
+
+    /// Run several queries on the power supply, the responses are in the same order as the queries.
+    pub fn query_all(&mut self, queries: &[Query]) -> anyhow::Result<Vec<Response>> {
+        queries.iter().map(|&query| self.query(query)).collect()
+    }
+
+    /// Read the status flags from the power supply
+    pub fn read_flags(&mut self) -> anyhow::Result<Flags> {
+        self.with_retries(|dev| {
+            let response = dev.run_command_response(&String::from(Query::Flags))?;
+            Ok(dev.quirks.status_bits.decode(response[0]))
+        })
+    }
+
     /// Read the output enable status from the power supply
     pub fn read_output_enable(&mut self) -> anyhow::Result<bool> {
-        let status = self.status()?;
-        Ok(status.flags.output.into())
+        let flags = self.read_flags()?;
+        Ok(flags.output.into())
     }
 
     /// Read the set voltage from the power supply
     pub fn read_set_voltage(&mut self) -> anyhow::Result<f32> {
-        let set_voltage = Self::printable_ascii(self.run_command_response("VSET1?")?).parse()?;
-        Ok(set_voltage)
+        self.query_value(Query::SetVoltage)
     }
 
     /// Read the set current from the power supply
     pub fn read_set_current(&mut self) -> anyhow::Result<f32> {
-        let set_current = Self::printable_ascii(self.run_command_response("ISET1?")?).parse()?;
-        Ok(set_current)
+        self.query_value(Query::SetCurrent)
     }
 
     /// Read the measured voltage from the power supply
     pub fn read_measured_voltage(&mut self) -> anyhow::Result<f32> {
-        let voltage = Self::printable_ascii(self.run_command_response("VOUT1?")?).parse()?;
-        Ok(voltage)
+        self.query_value(Query::Voltage)
     }
 
     /// Read the measured current from the power supply
     pub fn read_measured_current(&mut self) -> anyhow::Result<f32> {
-        let current = Self::printable_ascii(self.run_command_response("IOUT1?")?).parse()?;
-        Ok(current)
+        self.query_value(Query::Current)
     }
 
-    /// Helper function to extract printable ASCII characters from byte vector
-    fn printable_ascii(bytes: Vec<u8>) -> String {
-        bytes
-            .into_iter()
-            .filter(|&b| (32..=126).contains(&b))
-            .collect::<Vec<u8>>()
-            .into_iter()
-            .map(|b| b as char)
-            .collect()
+    fn query_value(&mut self, query: Query) -> anyhow::Result<f32> {
+        self.with_retries(|dev| {
+            let response = dev.run_command_response(&String::from(query))?;
+            dev.quirks.parse_value(&response)
+        })
+    }
+
+    /// Repeat a query until it succeeds or the configured number of retries is exhausted.
+    fn with_retries<T>(
+        &mut self,
+        mut query: impl FnMut(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match query(self) {
+                Err(e) if attempt < self.settings.retries => {
+                    attempt += 1;
+                    warn!("Query failed, retrying ({}): {:#}", attempt, e);
+                }
+                result => return result,
+            }
+        }
     }
 
     fn run_command_response(&mut self, command: &str) -> anyhow::Result<Vec<u8>> {
         let res = self.run_command(command)?;
-        anyhow::ensure!(!res.is_empty(), "PSU did not respond with data");
+        anyhow::ensure!(!res.is_empty(), Error::Timeout);
         Ok(res)
     }
 
     fn run_command(&mut self, command: &str) -> anyhow::Result<Vec<u8>> {
-        let bytes = command.as_bytes();
-        debug!("Sending command: {}", command);
-        if !self.serial.write(bytes)? == bytes.len() {
-            return Err(anyhow::anyhow!("Could not write command"));
+        match self.transfer(command) {
+            Err(e) if self.reconnect.is_some() && Self::is_connection_error(&e) => {
+                warn!("Lost connection to the power supply: {:#}", e);
+                self.reestablish()?;
+                self.transfer(command)
+            }
+            result => result,
+        }
+    }
+
+    fn is_connection_error(error: &anyhow::Error) -> bool {
+        error
+            .chain()
+            .any(|cause| cause.is::<io::Error>() || cause.is::<serialport::Error>())
+    }
+
+    fn reestablish(&mut self) -> anyhow::Result<()> {
+        let (Some(config), Some(port_name)) = (self.reconnect.clone(), self.port_name.clone())
+        else {
+            return Err(anyhow::anyhow!(
+                "Can't reconnect to a power supply with an unknown port"
+            ));
+        };
+        for delay in config.delays() {
+            thread::sleep(delay);
+            let Some(name) = reconnect::rediscover(&port_name, self.identity.as_ref()) else {
+                debug!("Power supply {} not found, retrying", port_name);
+                continue;
+            };
+            match self.settings.open(&name) {
+                Ok(serial) => {
+                    self.serial = serial;
+                    self.reconnects += 1;
+                    info!("Reconnected to the power supply on {}", name);
+                    self.port_name = Some(name);
+                    if config.restore_setpoints {
+                        self.restore_setpoints()?;
+                    }
+                    return Ok(());
+                }
+                Err(e) => debug!("Could not reopen {}: {:#}", name, e),
+            }
         }
+        Err(Error::DeviceNotFound(format!(
+            "Could not reconnect to the power supply after {} attempts",
+            config.attempts
+        ))
+        .into())
+    }
+
+    fn restore_setpoints(&mut self) -> anyhow::Result<()> {
+        let voltage = self.voltage_setpoint.map(Command::Voltage);
+        let current = self.current_setpoint.map(Command::Current);
+        for command in [voltage, current].into_iter().flatten() {
+            info!("Restoring setpoint {:?}", command);
+            self.transfer(&self.quirks.format(command))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_heartbeat(&mut self, heartbeat: Option<Box<dyn Fn() + Send>>) {
+        self.heartbeat = heartbeat;
+    }
+
+    /// A second handle of the serial port and the request switching the output off,
+    /// to switch it off while the power supply is in use, see [`watchdog::Watchdog`].
+    pub(crate) fn off_switch(&self) -> anyhow::Result<(Box<dyn serialport::SerialPort>, String)> {
+        let request = format!(
+            "{}{}",
+            self.quirks.format(Command::Power(Switch::Off)),
+            self.settings.terminator
+        );
+        Ok((self.serial.try_clone()?, request))
+    }
+
+    fn transfer(&mut self, command: &str) -> anyhow::Result<Vec<u8>> {
+        if let Some(last) = self.last_transfer {
+            thread::sleep(self.settings.command_delay.saturating_sub(last.elapsed()));
+        }
+        let request = format!("{}{}", command, self.settings.terminator);
+        debug!("Sending command: {}", command);
+        self.serial
+            .write_all(request.as_bytes())
+            .with_context(|| "Could not write command")?;
         self.serial.flush()?;
         let mut result: Vec<u8> = Vec::new();
         let mut is_done = false;
@@ -396,10 +680,26 @@ impl Ka3005p {
             result,
             String::from_utf8_lossy(&result)
         );
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(request.as_bytes(), &result) {
+                warn!("Could not record transfer: {}", e);
+            }
+        }
+        self.last_transfer = Some(time::Instant::now());
         Ok(result)
     }
 }
 
+impl Drop for Ka3005p {
+    fn drop(&mut self) {
+        for command in std::mem::take(&mut self.safe_state) {
+            if let Err(e) = self.execute(command) {
+                error!("Could not apply safe state {:?}: {:#}", command, e);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -475,4 +775,57 @@ mod tests {
             "ISET1:4.000".to_string()
         );
     }
+
+    #[test]
+    fn test_query_strings() {
+        assert_eq!(String::from(Query::Flags), "STATUS?".to_string());
+        assert_eq!(String::from(Query::Voltage), "VOUT1?".to_string());
+        assert_eq!(String::from(Query::Current), "IOUT1?".to_string());
+        assert_eq!(String::from(Query::SetVoltage), "VSET1?".to_string());
+        assert_eq!(String::from(Query::SetCurrent), "ISET1?".to_string());
+        assert_eq!(String::from(Query::Identity), "*IDN?".to_string());
+    }
+
+    #[test]
+    fn test_memory_slot() {
+        assert!(MemorySlot::new(0).is_err());
+        assert_eq!(MemorySlot::new(1).unwrap().get(), 1);
+        assert_eq!(MemorySlot::new(5).unwrap().get(), 5);
+        assert!(MemorySlot::new(6).is_err());
+        assert!(MemorySlot::try_from(256u32).is_err());
+        assert_eq!(
+            String::from(Command::Load("3".parse().unwrap())),
+            "RCL3".to_string()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let status = Status {
+            flags: Flags::new(0x51),
+            voltage: 12.0,
+            current: 0.305,
+            set_voltage: 12.0,
+            set_current: 1.0,
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        assert_eq!(serde_json::from_str::<Status>(&json).unwrap(), status);
+
+        let commands = vec![
+            Command::Power(Switch::On),
+            Command::Save(MemorySlot::new(2).unwrap()),
+            Command::Voltage(3.3),
+        ];
+        let json = serde_json::to_string(&commands).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"Power":"On"},{"Save":2},{"Voltage":3.3}]"#.to_string()
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<Command>>(&json).unwrap(),
+            commands
+        );
+        assert!(serde_json::from_str::<Command>(r#"{"Load":6}"#).is_err());
+    }
 }
@@ -0,0 +1,178 @@
+//! A scriptable serial port for unit tests of code using [`Ka3005p`](crate::Ka3005p),
+//! available with the `test-utils` feature.
+//!
+//! The mock records all requests and answers them with the configured replies.
+//! Requests without a configured reply (e.g. commands) are answered with a timeout, like the power supply does.
+//!
+//! ```
+//! use ka3005p::mock::{MockPort, Reply};
+//! use ka3005p::{Command, Ka3005p, Switch};
+//!
+//! let mock = MockPort::new();
+//! mock.on("VSET1?", Reply::data("12.00"));
+//!
+//! let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+//! assert_eq!(dev.read_set_voltage().unwrap(), 12.0);
+//! dev.execute(Command::Power(Switch::On)).unwrap();
+//!
+//! assert_eq!(mock.requests(), vec!["VSET1?", "OUT1"]);
+//! ```
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// How the mock answers a request
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reply {
+    /// Respond with these bytes
+    Data(Vec<u8>),
+    /// Don't respond, reading times out
+    Timeout,
+    /// Reading the response fails with an error of this kind
+    Error(io::ErrorKind),
+}
+
+impl Reply {
+    /// Respond with a text, e.g. `Reply::data("5.00")`
+    pub fn data(data: impl AsRef<[u8]>) -> Self {
+        Reply::Data(data.as_ref().to_vec())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    replies: HashMap<String, Reply>,
+    once: HashMap<String, VecDeque<Reply>>,
+    requests: Vec<String>,
+    pending: VecDeque<u8>,
+    error: Option<io::ErrorKind>,
+}
+
+/// A serial port for tests. Clones share their state, so one clone can be handed to
+/// [`Ka3005p::new_from_serial`](crate::Ka3005p::new_from_serial) while another is used to script and inspect it.
+#[derive(Debug, Default, Clone)]
+pub struct MockPort {
+    state: Arc<Mutex<State>>,
+    timeout: Duration,
+}
+
+impl MockPort {
+    /// A mock without any replies configured.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer every occurrence of `request` with `reply`.
+    pub fn on(&self, request: &str, reply: Reply) -> &Self {
+        self.state().replies.insert(request.to_string(), reply);
+        self
+    }
+
+    /// Answer the next occurrence of `request` with `reply`, takes precedence over [`MockPort::on`].
+    /// Several replies for the same request are used in the order they were added.
+    pub fn once(&self, request: &str, reply: Reply) -> &Self {
+        self.state()
+            .once
+            .entry(request.to_string())
+            .or_default()
+            .push_back(reply);
+        self
+    }
+
+    /// All requests written so far, as sent to the power supply (including terminators).
+    pub fn requests(&self) -> Vec<String> {
+        self.state().requests.clone()
+    }
+
+    /// Forget the recorded requests.
+    pub fn clear_requests(&self) {
+        self.state().requests.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn bytes_pending(&self) -> usize {
+        self.state().pending.len()
+    }
+}
+
+impl io::Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        let request = String::from_utf8_lossy(buf).into_owned();
+        let reply = state
+            .once
+            .get_mut(&request)
+            .and_then(VecDeque::pop_front)
+            .or_else(|| state.replies.get(&request).cloned());
+        match reply {
+            Some(Reply::Data(data)) => state.pending.extend(data),
+            Some(Reply::Error(kind)) => state.error = Some(kind),
+            Some(Reply::Timeout) | None => {}
+        }
+        state.requests.push(request);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        if let Some(kind) = state.error.take() {
+            return Err(io::Error::new(kind, "Mocked error"));
+        }
+        if state.pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "No response"));
+        }
+        let count = buf.len().min(state.pending.len());
+        for (dst, src) in buf.iter_mut().zip(state.pending.drain(..count)) {
+            *dst = src;
+        }
+        Ok(count)
+    }
+}
+
+// Clones share the state, like two handles of the same serial port
+crate::virtual_port::impl_serial_port!(MockPort, |port: &MockPort| Ok(
+    Box::new(port.clone()) as Box<dyn serialport::SerialPort>
+));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Ka3005p, Switch};
+
+    #[test]
+    fn test_requests() {
+        let mock = MockPort::new();
+        let mut dev = Ka3005p::builder()
+            .terminator("\n")
+            .open_serial(Box::new(mock.clone()));
+        dev.execute(Command::Voltage(5.0)).unwrap();
+        dev.execute(Command::Power(Switch::Off)).unwrap();
+        assert_eq!(mock.requests(), vec!["VSET1:5.00\n", "OUT0\n"]);
+        mock.clear_requests();
+        assert!(mock.requests().is_empty());
+    }
+
+    #[test]
+    fn test_replies() {
+        let mock = MockPort::new();
+        mock.on("ISET1?", Reply::data("1.000"))
+            .once("ISET1?", Reply::Timeout)
+            .once("ISET1?", Reply::Error(io::ErrorKind::BrokenPipe));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        assert!(dev.read_set_current().is_err());
+        let error = dev.read_set_current().unwrap_err();
+        assert!(format!("{:#}", error).contains("Mocked error"));
+        assert_eq!(dev.read_set_current().unwrap(), 1.0);
+        assert_eq!(dev.read_set_current().unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Backup and restore the settings stored in the memory slots of the power supply.
+//!
+//! ```no_run
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let presets = dev.read_presets().unwrap();
+//!
+//! let mut other = ka3005p::Ka3005p::new("/dev/ttyACM1").unwrap();
+//! other.write_presets(&presets).unwrap();
+//! ```
+use crate::{Command, Ka3005p, MemorySlot, Switch};
+
+/// Settings stored in a memory slot
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Preset {
+    /// The memory slot
+    pub slot: MemorySlot,
+    /// Target voltage in volts
+    pub voltage: f32,
+    /// Current limit in amps
+    pub current: f32,
+}
+
+impl Ka3005p {
+    /// Read the settings stored in a memory slot.
+    ///
+    /// Note: The slot is recalled to read its settings, this switches the output off
+    /// and replaces the active settings.
+    pub fn read_preset(&mut self, slot: MemorySlot) -> anyhow::Result<Preset> {
+        self.execute(Command::Load(slot))?;
+        Ok(Preset {
+            slot,
+            voltage: self.read_set_voltage()?,
+            current: self.read_set_current()?,
+        })
+    }
+
+    /// Store settings in a memory slot.
+    ///
+    /// Note: This replaces the active settings with the ones of the preset. The output is switched off
+    /// while storing and switched on again afterwards if it was on, so the load never sees the preset.
+    pub fn write_preset(&mut self, preset: &Preset) -> anyhow::Result<()> {
+        self.with_output_off(|dev| dev.store_preset(preset))
+    }
+
+    fn store_preset(&mut self, preset: &Preset) -> anyhow::Result<()> {
+        self.execute(Command::Voltage(preset.voltage))?;
+        self.execute(Command::Current(preset.current))?;
+        self.execute(Command::Save(preset.slot))
+    }
+
+    /// Read the settings of all memory slots supported by this power supply.
+    ///
+    /// Note: This switches the output off, the active settings are restored afterwards.
+    pub fn read_presets(&mut self) -> anyhow::Result<Vec<Preset>> {
+        self.preserving_settings(|dev| {
+            MemorySlot::up_to(dev.quirks().memory_slots)
+                .map(|slot| dev.read_preset(slot))
+                .collect()
+        })
+    }
+
+    /// Store the settings of several memory slots.
+    ///
+    /// Note: The output is switched off while storing, the active settings and the output state
+    /// are restored afterwards.
+    pub fn write_presets(&mut self, presets: &[Preset]) -> anyhow::Result<()> {
+        self.with_output_off(|dev| {
+            dev.preserving_settings(|dev| {
+                presets
+                    .iter()
+                    .try_for_each(|preset| dev.store_preset(preset))
+            })
+        })
+    }
+
+    /// Run `f` with the output switched off, it is switched on again afterwards if it was on.
+    /// If `f` fails the output stays off.
+    fn with_output_off<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let was_on = self.read_output_enable()?;
+        if was_on {
+            self.switch_output(Switch::Off)?;
+        }
+        let result = f(self)?;
+        if was_on {
+            self.switch_output(Switch::On)?;
+        }
+        Ok(result)
+    }
+
+    fn preserving_settings<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let voltage = self.read_set_voltage()?;
+        let current = self.read_set_current()?;
+        let result = f(self);
+        self.execute(Command::Voltage(voltage))?;
+        self.execute(Command::Current(current))?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    #[test]
+    fn test_read_presets() {
+        let mock = MockPort::new();
+        mock.on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("0.500"));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let presets = dev.read_presets().unwrap();
+        assert_eq!(presets.len(), 5);
+        assert_eq!(presets[4].slot, MemorySlot::new(5).unwrap());
+        assert_eq!(presets[4].voltage, 5.0);
+        let requests = mock.requests();
+        assert_eq!(
+            requests[..5],
+            ["VSET1?", "ISET1?", "RCL1", "VSET1?", "ISET1?"]
+        );
+        assert_eq!(
+            requests[requests.len() - 2..],
+            ["VSET1:5.00", "ISET1:0.500"]
+        );
+    }
+
+    #[test]
+    fn test_write_presets_with_output_off() {
+        let mock = MockPort::new();
+        mock.on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("0.500"))
+            .once("STATUS?", Reply::Data(vec![0x51]))
+            .once("STATUS?", Reply::Data(vec![0x11]))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let preset = Preset {
+            slot: MemorySlot::new(1).unwrap(),
+            voltage: 12.0,
+            current: 1.0,
+        };
+        dev.write_presets(&[preset]).unwrap();
+        assert_eq!(
+            mock.requests(),
+            [
+                "STATUS?",
+                "OUT0",
+                "STATUS?",
+                "VSET1?",
+                "ISET1?",
+                "VSET1:12.00",
+                "ISET1:1.000",
+                "SAV1",
+                "VSET1:5.00",
+                "ISET1:0.500",
+                "OUT1",
+                "STATUS?"
+            ]
+        );
+    }
+}
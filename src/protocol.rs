@@ -0,0 +1,341 @@
+//! Encoding and decoding of the serial protocol spoken by the power supplies.
+//!
+//! Besides formatting requests for the power supply, this allows decoding raw traffic
+//! into typed values, e.g. for sniffers, simulators or log analysis.
+//!
+//! ```
+//! use ka3005p::protocol::{split_requests, Request};
+//! use ka3005p::{Command, Query, Switch};
+//!
+//! let requests = split_requests("VSET1:5.00OUT1STATUS?").unwrap();
+//! assert_eq!(
+//!     requests,
+//!     vec![
+//!         Request::Command(Command::Voltage(5.0)),
+//!         Request::Command(Command::Power(Switch::On)),
+//!         Request::Query(Query::Flags),
+//!     ]
+//! );
+//! assert_eq!(requests[0].to_string(), "VSET1:5.00");
+//! ```
+use crate::quirks::Quirks;
+use crate::{Command, Error, Flags, MemorySlot, Switch};
+use std::fmt;
+use std::str::FromStr;
+
+/// Queries supported by the power supply.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Query {
+    /// Read the status flags
+    Flags,
+    /// Read the measured voltage
+    Voltage,
+    /// Read the measured current
+    Current,
+    /// Read the target voltage
+    SetVoltage,
+    /// Read the current limit
+    SetCurrent,
+    /// Read the identification of the power supply
+    Identity,
+}
+
+/// Typed responses to a [`Query`].
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Response {
+    /// Status flags
+    Flags(Flags),
+    /// Measured voltage in volts
+    Voltage(f32),
+    /// Measured current in amps
+    Current(f32),
+    /// Target voltage in volts
+    SetVoltage(f32),
+    /// Current limit in amps
+    SetCurrent(f32),
+    /// Identification, e.g. `KORAD KA3005P V5.8 SN:03379314`
+    Identity(String),
+}
+
+impl std::convert::From<Query> for String {
+    fn from(q: Query) -> Self {
+        match q {
+            Query::Flags => String::from("STATUS?"),
+            Query::Voltage => String::from("VOUT1?"),
+            Query::Current => String::from("IOUT1?"),
+            Query::SetVoltage => String::from("VSET1?"),
+            Query::SetCurrent => String::from("ISET1?"),
+            Query::Identity => String::from("*IDN?"),
+        }
+    }
+}
+
+impl Response {
+    /// Decode the raw response of the power supply to `query`.
+    pub fn parse(query: Query, bytes: &[u8], quirks: &Quirks) -> anyhow::Result<Response> {
+        let response = match query {
+            Query::Flags => {
+                let flags = bytes.first().ok_or(Error::Timeout)?;
+                Response::Flags(quirks.status_bits.decode(*flags))
+            }
+            Query::Voltage => Response::Voltage(quirks.parse_value(bytes)?),
+            Query::Current => Response::Current(quirks.parse_value(bytes)?),
+            Query::SetVoltage => Response::SetVoltage(quirks.parse_value(bytes)?),
+            Query::SetCurrent => Response::SetCurrent(quirks.parse_value(bytes)?),
+            Query::Identity => Response::Identity(quirks.response_text(bytes).trim().to_string()),
+        };
+        Ok(response)
+    }
+
+    /// Encode the response the way the power supply would send it, e.g. for simulators.
+    pub fn format(&self, quirks: &Quirks) -> Vec<u8> {
+        match self {
+            Response::Flags(flags) => vec![flags.flags],
+            Response::Voltage(v) | Response::SetVoltage(v) => {
+                format!("{:.*}", quirks.voltage_decimals, v).into_bytes()
+            }
+            Response::Current(i) | Response::SetCurrent(i) => {
+                format!("{:.*}", quirks.current_decimals, i).into_bytes()
+            }
+            Response::Identity(identity) => identity.clone().into_bytes(),
+        }
+    }
+}
+
+/// Prefix of a command on the wire and how to build the command from its argument
+type Mnemonic<T> = (&'static str, fn(T) -> Command);
+
+/// A single request sent to the power supply
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Request {
+    /// A command which changes the state of the power supply, no response is sent
+    Command(Command),
+    /// A query the power supply responds to
+    Query(Query),
+}
+
+impl Request {
+    /// Encode the request for a model with the given quirks.
+    pub fn format(&self, quirks: &Quirks) -> String {
+        match self {
+            Request::Command(command) => quirks.format(*command),
+            Request::Query(query) => String::from(*query),
+        }
+    }
+
+    /// Decode the beginning of `s`, returns the request and the number of bytes it occupied.
+    fn parse_prefix(s: &str) -> anyhow::Result<(Request, usize)> {
+        const QUERIES: [Query; 6] = [
+            Query::Flags,
+            Query::Voltage,
+            Query::Current,
+            Query::SetVoltage,
+            Query::SetCurrent,
+            Query::Identity,
+        ];
+        let upper = s.to_ascii_uppercase();
+        // Queries first, as e.g. "VSET1?" shares its prefix with "VSET1:"
+        for query in QUERIES {
+            let wire = String::from(query);
+            if upper.starts_with(&wire) {
+                return Ok((Request::Query(query), wire.len()));
+            }
+        }
+        const SWITCHES: [Mnemonic<Switch>; 4] = [
+            ("OUT", Command::Power),
+            ("OVP", Command::Ovp),
+            ("OCP", Command::Ocp),
+            ("BEEP", Command::Beep),
+        ];
+        for (prefix, command) in SWITCHES {
+            if let Some(rest) = upper.strip_prefix(prefix) {
+                let switch = match rest.chars().next() {
+                    Some('1') => Switch::On,
+                    Some('0') => Switch::Off,
+                    _ => anyhow::bail!("Expected 0 or 1 after {:?}", prefix),
+                };
+                return Ok((Request::Command(command(switch)), prefix.len() + 1));
+            }
+        }
+        const SLOTS: [Mnemonic<MemorySlot>; 2] = [("SAV", Command::Save), ("RCL", Command::Load)];
+        for (prefix, command) in SLOTS {
+            if let Some(rest) = upper.strip_prefix(prefix) {
+                let digit = rest.get(..1).unwrap_or_default();
+                let slot = MemorySlot::from_str(digit)?;
+                return Ok((Request::Command(command(slot)), prefix.len() + 1));
+            }
+        }
+        const VALUES: [Mnemonic<f32>; 2] =
+            [("VSET1:", Command::Voltage), ("ISET1:", Command::Current)];
+        for (prefix, command) in VALUES {
+            if let Some(rest) = upper.strip_prefix(prefix) {
+                let length = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(rest.len());
+                let value = rest[..length]
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Invalid value after {:?}: {}", prefix, e))?;
+                return Ok((Request::Command(command(value)), prefix.len() + length));
+            }
+        }
+        anyhow::bail!("Unknown request {:?}", s)
+    }
+}
+
+impl FromStr for Request {
+    type Err = anyhow::Error;
+    /// Decode a single request, surrounding whitespace (e.g. terminators) is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (request, length) = Request::parse_prefix(s)?;
+        anyhow::ensure!(
+            length == s.len(),
+            "Unexpected trailing data {:?}",
+            &s[length..]
+        );
+        Ok(request)
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&Quirks::default()))
+    }
+}
+
+/// Decode a stream of requests, which the power supply accepts without any separator,
+/// e.g. `"VSET1:5.00OUT1"`. Whitespace between the requests is ignored.
+pub fn split_requests(s: &str) -> anyhow::Result<Vec<Request>> {
+    let mut requests = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let (request, length) = Request::parse_prefix(rest)?;
+        requests.push(request);
+        rest = rest[length..].trim_start();
+    }
+    Ok(requests)
+}
+
+/// Render raw traffic as printable text, non printable bytes are escaped (e.g. `\n`, `\x01`).
+pub fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(char::from)
+        .collect()
+}
+
+/// Reverse of [`escape`].
+pub fn unescape(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some(c @ ('\\' | '\'' | '"')) => c as u8,
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                anyhow::ensure!(hex.len() == 2, "Incomplete escape \\x{}", hex);
+                u8::from_str_radix(&hex, 16)
+                    .map_err(|e| anyhow::anyhow!("Invalid escape \\x{}: {}", hex, e))?
+            }
+            other => anyhow::bail!("Invalid escape sequence after \\: {:?}", other),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_requests() -> Vec<Request> {
+        let slot = MemorySlot::new(3).unwrap();
+        vec![
+            Request::Command(Command::Power(Switch::On)),
+            Request::Command(Command::Power(Switch::Off)),
+            Request::Command(Command::Beep(Switch::On)),
+            Request::Command(Command::Ovp(Switch::Off)),
+            Request::Command(Command::Ocp(Switch::On)),
+            Request::Command(Command::Save(slot)),
+            Request::Command(Command::Load(slot)),
+            Request::Command(Command::Voltage(12.34)),
+            Request::Command(Command::Current(0.305)),
+            Request::Query(Query::Flags),
+            Request::Query(Query::Voltage),
+            Request::Query(Query::Current),
+            Request::Query(Query::SetVoltage),
+            Request::Query(Query::SetCurrent),
+            Request::Query(Query::Identity),
+        ]
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        for request in all_requests() {
+            assert_eq!(request.to_string().parse::<Request>().unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn test_split_requests() {
+        let wire: String = all_requests().iter().map(|r| r.to_string()).collect();
+        assert_eq!(split_requests(&wire).unwrap(), all_requests());
+        assert_eq!(
+            split_requests("vset1:5\nout1\n").unwrap(),
+            vec![
+                Request::Command(Command::Voltage(5.0)),
+                Request::Command(Command::Power(Switch::On))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        assert!("OUT2".parse::<Request>().is_err());
+        assert!("RCL9".parse::<Request>().is_err());
+        assert!("VSET1:".parse::<Request>().is_err());
+        assert!("OUT1X".parse::<Request>().is_err());
+        assert!("HELLO".parse::<Request>().is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        let raw = b"VSET1?\r\n\x00\\\"\t";
+        assert_eq!(escape(raw), "VSET1?\\r\\n\\x00\\\\\\\"\\t");
+        assert_eq!(unescape(&escape(raw)).unwrap(), raw);
+        assert!(unescape("\\q").is_err());
+        assert!(unescape("\\x4").is_err());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let quirks = Quirks::default();
+        let responses = [
+            (Query::Flags, Response::Flags(Flags::from(0x51))),
+            (Query::Voltage, Response::Voltage(12.0)),
+            (Query::Current, Response::Current(0.305)),
+            (Query::SetVoltage, Response::SetVoltage(5.5)),
+            (Query::SetCurrent, Response::SetCurrent(1.0)),
+            (
+                Query::Identity,
+                Response::Identity("KORAD KA3005P V5.8 SN:03379314".to_string()),
+            ),
+        ];
+        for (query, response) in responses {
+            let bytes = response.format(&quirks);
+            assert_eq!(Response::parse(query, &bytes, &quirks).unwrap(), response);
+        }
+    }
+}
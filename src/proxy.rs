@@ -0,0 +1,271 @@
+//! Forward the traffic of another program (e.g. the vendor software) to the power supply
+//! and decode it, which helps to find out about undocumented commands.
+//!
+//! ```no_run
+//! use ka3005p::proxy::Proxy;
+//!
+//! let device = ka3005p::Ka3005p::builder().open_port().unwrap();
+//! let mut proxy = Proxy::with_pty(device).unwrap();
+//! println!("Connect to {}", proxy.host_name().unwrap());
+//! proxy.run(|exchange| println!("{}", exchange)).unwrap();
+//! ```
+use crate::protocol::{escape, split_requests, Request, Response};
+use crate::quirks::Quirks;
+use serialport::SerialPort;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How long a single read blocks while waiting for data from either side
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A request sent to the power supply together with the response it sent back.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Exchange {
+    /// Raw bytes sent by the host
+    pub request: Vec<u8>,
+    /// Raw bytes sent by the power supply, empty for commands
+    pub response: Vec<u8>,
+}
+
+impl Exchange {
+    /// Decode the requests, several commands are often sent without a pause in between.
+    pub fn requests(&self) -> anyhow::Result<Vec<Request>> {
+        split_requests(&String::from_utf8_lossy(&self.request))
+    }
+
+    /// Decode the response to the last query of the request, if there was one.
+    pub fn response(&self, quirks: &Quirks) -> Option<anyhow::Result<Response>> {
+        if self.response.is_empty() {
+            return None;
+        }
+        let query = self.requests().map(|requests| {
+            requests.iter().rev().find_map(|request| match request {
+                Request::Query(query) => Some(*query),
+                Request::Command(_) => None,
+            })
+        });
+        match query {
+            Ok(Some(query)) => Some(Response::parse(query, &self.response, quirks)),
+            Ok(None) => Some(Err(anyhow::anyhow!("Response without query"))),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl fmt::Display for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\" ", escape(&self.request))?;
+        match self.requests() {
+            Ok(requests) => write!(f, "{:?}", requests)?,
+            Err(e) => write!(f, "[{}]", e)?,
+        }
+        if let Some(response) = self.response(&Quirks::default()) {
+            write!(f, " -> \"{}\" ", escape(&self.response))?;
+            match response {
+                Ok(response) => write!(f, "{:?}", response)?,
+                Err(e) => write!(f, "[{}]", e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Forwards bytes between a host (e.g. a pseudo terminal) and the power supply.
+pub struct Proxy {
+    host: Box<dyn SerialPort>,
+    device: Box<dyn SerialPort>,
+    /// Keeps the pseudo terminal open while no other program is connected
+    pty: Option<Box<dyn SerialPort>>,
+    idle: Duration,
+}
+
+impl Proxy {
+    /// Forward traffic between two serial ports, e.g. one end of a virtual null modem and the power supply.
+    pub fn new(host: Box<dyn SerialPort>, device: Box<dyn SerialPort>) -> Self {
+        Proxy {
+            host,
+            device,
+            pty: None,
+            idle: Duration::from_millis(60),
+        }
+    }
+
+    /// Create a pseudo terminal other programs can connect to, see [`Proxy::host_name`].
+    #[cfg(unix)]
+    pub fn with_pty(device: Box<dyn SerialPort>) -> anyhow::Result<Self> {
+        let (master, slave) = serialport::TTYPort::pair()?;
+        let mut proxy = Proxy::new(Box::new(master), device);
+        proxy.pty = Some(Box::new(slave));
+        Ok(proxy)
+    }
+
+    /// Time without traffic after which an exchange is considered complete (default: 60 ms)
+    pub fn idle(mut self, idle: Duration) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    /// The port other programs need to connect to.
+    pub fn host_name(&self) -> Option<String> {
+        match &self.pty {
+            Some(pty) => pty.name(),
+            None => self.host.name(),
+        }
+    }
+
+    /// Forward traffic until an error occurs, every exchange is passed to `on_exchange`.
+    pub fn run(&mut self, mut on_exchange: impl FnMut(&Exchange)) -> anyhow::Result<()> {
+        self.host.set_timeout(POLL_INTERVAL)?;
+        self.device.set_timeout(POLL_INTERVAL)?;
+        let mut exchange = Exchange::default();
+        let mut last_activity = Instant::now();
+        loop {
+            let request = forward(self.host.as_mut(), self.device.as_mut())?;
+            if !request.is_empty() && !exchange.response.is_empty() {
+                // The host sent the next request before the idle time passed
+                on_exchange(&std::mem::take(&mut exchange));
+            }
+            let response = forward(self.device.as_mut(), self.host.as_mut())?;
+            if request.is_empty() && response.is_empty() {
+                if !exchange.request.is_empty() && last_activity.elapsed() >= self.idle {
+                    on_exchange(&std::mem::take(&mut exchange));
+                }
+                continue;
+            }
+            exchange.request.extend(request);
+            exchange.response.extend(response);
+            last_activity = Instant::now();
+        }
+    }
+}
+
+/// Copy the pending bytes from one port to the other, returns the copied bytes.
+fn forward(from: &mut dyn SerialPort, to: &mut dyn SerialPort) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = vec![0; 512];
+    let count = match from.read(&mut buffer) {
+        Ok(count) => count,
+        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => 0,
+        Err(e) => return Err(e.into()),
+    };
+    buffer.truncate(count);
+    if !buffer.is_empty() {
+        to.write_all(&buffer)?;
+        to.flush()?;
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+    use crate::protocol::Query;
+    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// The program connected to the proxy, its reads are scripted and its writes recorded
+    struct HostPort {
+        reads: VecDeque<io::Result<Vec<u8>>>,
+        written: Arc<Mutex<Vec<u8>>>,
+        timeout: Duration,
+    }
+
+    impl HostPort {
+        fn bytes_pending(&self) -> usize {
+            0
+        }
+    }
+
+    impl Read for HostPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let data = self
+                .reads
+                .pop_front()
+                .unwrap_or_else(|| Err(io::ErrorKind::BrokenPipe.into()))?;
+            buf[..data.len()].copy_from_slice(&data);
+            Ok(data.len())
+        }
+    }
+
+    impl Write for HostPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    crate::virtual_port::impl_serial_port!(HostPort);
+
+    #[test]
+    fn test_forward() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let host = HostPort {
+            // The query arrives in two reads, then the host stays quiet and finally disconnects
+            reads: VecDeque::from([
+                Ok(b"VSET".to_vec()),
+                Ok(b"1?".to_vec()),
+                Err(io::ErrorKind::TimedOut.into()),
+            ]),
+            written: written.clone(),
+            timeout: Duration::ZERO,
+        };
+        let device = MockPort::new();
+        // The mock sees every forwarded chunk as a separate request
+        device.on("1?", Reply::data("5.00"));
+        let mut proxy = Proxy::new(Box::new(host), Box::new(device.clone())).idle(Duration::ZERO);
+        let mut exchanges = Vec::new();
+        let error = proxy.run(|exchange| exchanges.push(exchange.clone()));
+        assert!(error.is_err());
+        assert_eq!(device.requests(), ["VSET", "1?"]);
+        assert_eq!(*written.lock().unwrap(), b"5.00");
+        assert_eq!(
+            exchanges,
+            [Exchange {
+                request: b"VSET1?".to_vec(),
+                response: b"5.00".to_vec(),
+            }]
+        );
+        assert_eq!(
+            exchanges[0].requests().unwrap(),
+            [Request::Query(Query::SetVoltage)]
+        );
+        assert_eq!(
+            exchanges[0].response(&Quirks::default()).unwrap().unwrap(),
+            Response::SetVoltage(5.0)
+        );
+    }
+
+    #[test]
+    fn test_exchange_display() {
+        let query = Exchange {
+            request: b"VSET1?".to_vec(),
+            response: b"5.00".to_vec(),
+        };
+        assert_eq!(
+            query.to_string(),
+            "\"VSET1?\" [Query(SetVoltage)] -> \"5.00\" SetVoltage(5.0)"
+        );
+        let commands = Exchange {
+            request: b"OUT1BEEP0".to_vec(),
+            response: Vec::new(),
+        };
+        assert_eq!(
+            commands.to_string(),
+            "\"OUT1BEEP0\" [Command(Power(On)), Command(Beep(Off))]"
+        );
+        let unknown = Exchange {
+            request: b"LOCK1\n".to_vec(),
+            response: Vec::new(),
+        };
+        assert_eq!(
+            unknown.to_string(),
+            "\"LOCK1\\n\" [Unknown request \"LOCK1\\n\"]"
+        );
+    }
+}
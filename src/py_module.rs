@@ -1,47 +1,427 @@
 //! doc
-use crate::{find_serial_port, list_serial_ports, Command, Ka3005p, Status, Switch};
-use anyhow::Error;
-use pyo3::exceptions::PyException;
+use crate::acquisition::{Sample, Schedule};
+use crate::quirks::Quirks;
+use crate::reconnect::Reconnect;
+use crate::slew::SlewRate;
+use crate::{list_serial_ports, Channel, Command, Flags, Ka3005p, Lock, Mode, Status, Switch};
+use pyo3::exceptions::{PyException, PyValueError};
 use pyo3::prelude::*;
-use pyo3::PyErr;
+use pyo3::types::PyDict;
+use pyo3::{IntoPyObjectExt, PyErr};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
 
 // TODO's:
 //
 // * Build python extension based on feature flag
-// * Add custom Exception to the module see: https://pyo3.rs/main/exception
-// * Expose IDN
-// * Expose Mode status
-// * Expose Channel status
-// * Expose Lock status
-// * Export Lock, Channel, Mode and Switch Enum types to python
+// * Export Switch Enum type to python
 // * Wrap in python (src) based project
 //   - provide typeshed
 //   - provide readme
 //   - provide usage examples
 //
 
-struct Ka3005pError(Error);
+pyo3::create_exception!(
+    ka3005p,
+    Error,
+    PyException,
+    "Base class of all errors raised by ka3005p."
+);
+pyo3::create_exception!(
+    ka3005p,
+    DeviceNotFound,
+    Error,
+    "No (compatible) power supply was found."
+);
+pyo3::create_exception!(ka3005p, Timeout, Error, "The power supply didn't respond.");
+pyo3::create_exception!(
+    ka3005p,
+    ProtocolError,
+    Error,
+    "The power supply responded with data which couldn't be decoded."
+);
+pyo3::create_exception!(
+    ka3005p,
+    LimitExceeded,
+    Error,
+    "A value is outside of the range supported by the power supply."
+);
+
+struct Ka3005pError(anyhow::Error);
 
 impl From<Ka3005pError> for PyErr {
     fn from(error: Ka3005pError) -> Self {
-        PyException::new_err(error.0.to_string())
+        let message = format!("{:#}", error.0);
+        match crate::Error::classify(&error.0) {
+            Some(crate::Error::DeviceNotFound(_)) => DeviceNotFound::new_err(message),
+            Some(crate::Error::Timeout) => Timeout::new_err(message),
+            Some(crate::Error::Protocol(_)) => ProtocolError::new_err(message),
+            Some(crate::Error::LimitExceeded(_)) => LimitExceeded::new_err(message),
+            None => Error::new_err(message),
+        }
     }
 }
 
-impl From<Error> for Ka3005pError {
-    fn from(error: Error) -> Self {
+impl From<anyhow::Error> for Ka3005pError {
+    fn from(error: anyhow::Error) -> Self {
         Self(error)
     }
 }
 
-#[pyclass]
+/// Convert seconds passed from python into a duration.
+fn seconds(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Slew rate from the `slew_voltage` (V/s) and `slew_current` (A/s) arguments, `None` if neither is limited.
+fn slew_rate(voltage: Option<f32>, current: Option<f32>) -> PyResult<Option<SlewRate>> {
+    if voltage.into_iter().chain(current).any(|rate| rate <= 0.0) {
+        return Err(PyValueError::new_err("Slew rates must be positive"));
+    }
+    Ok((voltage.is_some() || current.is_some()).then(|| SlewRate {
+        voltage,
+        current,
+        ..SlewRate::default()
+    }))
+}
+
+/// CC or CV mode of a channel.
+#[pyclass(name = "Mode", eq, eq_int, frozen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PyMode {
+    /// Constant current
+    #[pyo3(name = "CC")]
+    Cc,
+    /// Constant voltage
+    #[pyo3(name = "CV")]
+    Cv,
+}
+
+impl PyMode {
+    fn name(&self) -> &'static str {
+        match self {
+            PyMode::Cc => "Mode.CC",
+            PyMode::Cv => "Mode.CV",
+        }
+    }
+}
+
+impl From<Mode> for PyMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Cc => PyMode::Cc,
+            Mode::Cv => PyMode::Cv,
+        }
+    }
+}
+
+/// Whether the front panel is locked.
+#[pyclass(name = "Lock", eq, eq_int, frozen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PyLock {
+    /// Buttons are ignored, serial commands still work
+    #[pyo3(name = "LOCKED")]
+    Locked,
+    /// Buttons work
+    #[pyo3(name = "UNLOCKED")]
+    Unlocked,
+}
+
+impl PyLock {
+    fn name(&self) -> &'static str {
+        match self {
+            PyLock::Locked => "Lock.LOCKED",
+            PyLock::Unlocked => "Lock.UNLOCKED",
+        }
+    }
+}
+
+impl From<Lock> for PyLock {
+    fn from(lock: Lock) -> Self {
+        match lock {
+            Lock::Locked => PyLock::Locked,
+            Lock::Unlocked => PyLock::Unlocked,
+        }
+    }
+}
+
+/// Channel of the power supply.
+#[pyclass(name = "Channel", eq, eq_int, frozen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PyChannel {
+    /// Channel one
+    #[pyo3(name = "ONE")]
+    One,
+    /// Channel two (if your device has one)
+    #[pyo3(name = "TWO")]
+    Two,
+}
+
+impl From<PyChannel> for Channel {
+    fn from(channel: PyChannel) -> Self {
+        match channel {
+            PyChannel::One => Channel::One,
+            PyChannel::Two => Channel::Two,
+        }
+    }
+}
+
+/// Status flags of the power supply.
+#[pyclass(name = "Flags", frozen, get_all)]
+#[derive(Debug, Clone)]
+struct PyFlags {
+    /// Mode of channel 1
+    channel1: PyMode,
+    /// Mode of channel 2
+    channel2: PyMode,
+    /// Beeping enabled
+    beep: bool,
+    /// Front panel lock
+    lock: PyLock,
+    /// Output enabled
+    output: bool,
+    /// The raw status byte
+    raw: u8,
+}
+
+impl From<Flags> for PyFlags {
+    fn from(flags: Flags) -> Self {
+        PyFlags {
+            channel1: flags.channel1.into(),
+            channel2: flags.channel2.into(),
+            beep: flags.beep.into(),
+            lock: flags.lock.into(),
+            output: flags.output.into(),
+            raw: flags.flags,
+        }
+    }
+}
+
+#[pymethods]
+impl PyFlags {
+    /// Get the mode of a channel.
+    ///
+    /// Args:
+    ///     channel: The channel.
+    fn mode(&self, channel: PyChannel) -> PyMode {
+        match Channel::from(channel) {
+            Channel::One => self.channel1,
+            Channel::Two => self.channel2,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        let py_bool = |b: bool| if b { "True" } else { "False" };
+        format!(
+            "Flags(channel1={}, channel2={}, beep={}, lock={}, output={})",
+            self.channel1.name(),
+            self.channel2.name(),
+            py_bool(self.beep),
+            self.lock.name(),
+            py_bool(self.output)
+        )
+    }
+}
+
+/// A single measurement of an acquisition.
+#[pyclass(name = "Sample", frozen, get_all)]
+#[derive(Debug, Clone)]
+struct PySample {
+    /// Seconds since the acquisition started
+    time: f64,
+    /// Measured voltage in volts
+    voltage: f32,
+    /// Measured current in amps
+    current: f32,
+    /// Mode of channel 1
+    mode: PyMode,
+}
+
+impl From<Sample> for PySample {
+    fn from(sample: Sample) -> Self {
+        PySample {
+            time: sample.time.as_secs_f64(),
+            voltage: sample.voltage,
+            current: sample.current,
+            mode: sample.mode.into(),
+        }
+    }
+}
+
+#[pymethods]
+impl PySample {
+    fn __repr__(&self) -> String {
+        format!(
+            "Sample(time={:?}, voltage={:?}, current={:?}, mode={})",
+            self.time,
+            self.voltage,
+            self.current,
+            self.mode.name()
+        )
+    }
+}
+
+/// Measurements, settings and flags of the power supply.
+#[pyclass(name = "Status", frozen)]
+#[derive(Debug, Clone)]
+struct PyStatus {
+    /// Measured voltage in volts
+    #[pyo3(get)]
+    voltage: f32,
+    /// Measured current in amps
+    #[pyo3(get)]
+    current: f32,
+    /// Target voltage in volts
+    #[pyo3(get)]
+    set_voltage: f32,
+    /// Current limit in amps
+    #[pyo3(get)]
+    set_current: f32,
+    /// Status flags
+    #[pyo3(get)]
+    flags: PyFlags,
+    status: Status,
+}
+
+impl From<Status> for PyStatus {
+    fn from(status: Status) -> Self {
+        PyStatus {
+            voltage: status.voltage,
+            current: status.current,
+            set_voltage: status.set_voltage,
+            set_current: status.set_current,
+            flags: status.flags.into(),
+            status,
+        }
+    }
+}
+
+#[pymethods]
+impl PyStatus {
+    fn __str__(&self) -> String {
+        self.status.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Status(voltage={:?}, current={:?}, set_voltage={:?}, set_current={:?}, flags={})",
+            self.voltage,
+            self.current,
+            self.set_voltage,
+            self.set_current,
+            self.flags.__repr__()
+        )
+    }
+}
+
+/// The power supply, shared between Python threads and the threads running async calls.
+type Device = Arc<Mutex<Ka3005p>>;
+
+fn lock(device: &Mutex<Ka3005p>) -> MutexGuard<'_, Ka3005p> {
+    device.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Open a power supply with the settings passed to the constructors.
+#[allow(clippy::too_many_arguments)]
+fn open(
+    serial_port: Option<&str>,
+    baud_rate: u32,
+    timeout: f64,
+    command_delay: f64,
+    terminator: &str,
+    retries: u32,
+    reconnect: bool,
+    model: Option<&str>,
+    slew_rate: Option<SlewRate>,
+) -> PyResult<Device> {
+    let mut builder = Ka3005p::builder()
+        .baud_rate(baud_rate)
+        .timeout(seconds(timeout)?)
+        .command_delay(seconds(command_delay)?)
+        .terminator(terminator)
+        .retries(retries);
+    if let Some(port) = serial_port {
+        builder = builder.port_name(port);
+    }
+    if reconnect {
+        builder = builder.reconnect(Reconnect::default());
+    }
+    if let Some(model) = model {
+        builder = builder.quirks(Quirks::for_identity(model));
+    }
+    if let Some(slew_rate) = slew_rate {
+        builder = builder.slew_rate(slew_rate);
+    }
+    let device = builder.open().map_err(Into::<Ka3005pError>::into)?;
+    Ok(Arc::new(Mutex::new(device)))
+}
+
+/// What happens when a `with` block is entered and left.
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    /// Voltage applied when entering
+    voltage: Option<f32>,
+    /// Current limit applied when entering
+    current: Option<f32>,
+    /// Restore the settings active before entering when leaving
+    restore: bool,
+}
+
+impl Session {
+    /// Apply the initial settings, returns the settings to restore when leaving.
+    fn enter(&self, device: &mut Ka3005p) -> anyhow::Result<Option<(f32, f32)>> {
+        let saved = match self.restore {
+            true => Some((device.read_set_voltage()?, device.read_set_current()?)),
+            false => None,
+        };
+        if let Some(v) = self.voltage {
+            device.execute(Command::Voltage(v))?;
+        }
+        if let Some(i) = self.current {
+            device.execute(Command::Current(i))?;
+        }
+        Ok(saved)
+    }
+
+    /// Switch the output off and restore the saved settings.
+    /// Every step is tried, even if switching the output off failed.
+    fn exit(device: &mut Ka3005p, saved: Option<(f32, f32)>) -> anyhow::Result<()> {
+        let mut result = device.execute(Command::Power(Switch::Off));
+        if let Some((voltage, current)) = saved {
+            let restored = device
+                .execute(Command::Voltage(voltage))
+                .and_then(|_| device.execute(Command::Current(current)));
+            result = result.and(restored);
+        }
+        result
+    }
+}
+
+#[pyclass(frozen)]
 /// Represents a power supply device.
+///
+/// The object can be shared between threads, the GIL is released while waiting for the power supply.
 struct PowerSupply {
-    inner: Ka3005p,
+    device: Device,
+    session: Session,
+    /// Settings active before entering the `with` block
+    saved: Mutex<Option<(f32, f32)>>,
 }
 
 // helper methods
 impl PowerSupply {
+    /// Run an operation on the power supply without holding the GIL, so other Python threads keep running.
+    fn run<T: Send>(
+        &self,
+        py: Python<'_>,
+        operation: impl FnOnce(&mut Ka3005p) -> anyhow::Result<T> + Send,
+    ) -> PyResult<T> {
+        Ok(py
+            .detach(|| operation(&mut lock(&self.device)))
+            .map_err(Into::<Ka3005pError>::into)?)
+    }
+
     /// Execute a command on the power supply.
     ///
     /// Args:
@@ -49,42 +429,111 @@ impl PowerSupply {
     ///
     /// Returns:
     ///     Result of executing the command.
-    fn _execute(&mut self, command: Command) -> PyResult<()> {
-        Ok(self
-            .inner
-            .execute(command)
-            .map_err(Into::<Ka3005pError>::into)?)
+    fn _execute(&self, py: Python<'_>, command: Command) -> PyResult<()> {
+        self.run(py, |dev| dev.execute(command))
     }
 
-    /// Get the status of the power supply.
+    /// Get the status flags of the power supply.
     ///
     /// Returns:
-    ///     Status of the power supply.
-    fn _status(&mut self) -> PyResult<Status> {
-        Ok(self.inner.status().map_err(Into::<Ka3005pError>::into)?)
+    ///     Flags of the power supply.
+    fn _flags(&self, py: Python<'_>) -> PyResult<Flags> {
+        self.run(py, Ka3005p::read_flags)
     }
 }
 
 #[pymethods]
 impl PowerSupply {
     #[new]
+    #[pyo3(signature = (serial_port=None, *, baud_rate=9600, timeout=0.06, command_delay=0.0, terminator="", retries=0, reconnect=false, model=None, slew_voltage=None, slew_current=None, voltage=None, current=None, restore=false))]
     /// Initialize a new PowerSupply instance.
     ///
     /// Args:
     ///     serial_port: Optional serial port for communication.
+    ///     baud_rate: Baud rate of the serial port.
+    ///     timeout: Seconds to wait for a response of the power supply.
+    ///     command_delay: Minimum seconds between two commands.
+    ///     terminator: Terminator appended to every command, e.g. "\n".
+    ///     retries: How often a query is repeated if the power supply didn't respond with valid data.
+    ///     reconnect: Reconnect automatically if the connection to the power supply is lost.
+    ///     model: Use the quirks of this model instead of detecting them via *IDN?.
+    ///     slew_voltage: Maximum rate of change of the voltage in V/s while the output is on.
+    ///     slew_current: Maximum rate of change of the current limit in A/s while the output is on.
+    ///     voltage: Voltage applied when entering a `with` block.
+    ///     current: Current limit applied when entering a `with` block.
+    ///     restore: Restore the voltage and current limit active before entering a `with` block when leaving it.
     ///
     /// Returns:
     ///     New instance of PowerSupply.
-    fn new(serial_port: Option<&str>) -> PyResult<Self> {
-        let supply = match serial_port {
-            Some(port) => PowerSupply {
-                inner: Ka3005p::new(port).map_err(Into::<Ka3005pError>::into)?,
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        serial_port: Option<&str>,
+        baud_rate: u32,
+        timeout: f64,
+        command_delay: f64,
+        terminator: &str,
+        retries: u32,
+        reconnect: bool,
+        model: Option<&str>,
+        slew_voltage: Option<f32>,
+        slew_current: Option<f32>,
+        voltage: Option<f32>,
+        current: Option<f32>,
+        restore: bool,
+    ) -> PyResult<Self> {
+        let device = open(
+            serial_port,
+            baud_rate,
+            timeout,
+            command_delay,
+            terminator,
+            retries,
+            reconnect,
+            model,
+            slew_rate(slew_voltage, slew_current)?,
+        )?;
+        Ok(PowerSupply {
+            device,
+            session: Session {
+                voltage,
+                current,
+                restore,
             },
-            None => PowerSupply {
-                inner: find_serial_port().map_err(Into::<Ka3005pError>::into)?,
-            },
-        };
-        Ok(supply)
+            saved: Mutex::new(None),
+        })
+    }
+
+    /// Apply the initial voltage and current limit, if configured.
+    ///
+    /// Returns:
+    ///     The power supply.
+    fn __enter__(slf: Bound<'_, Self>) -> PyResult<Bound<'_, Self>> {
+        let this = slf.get();
+        let session = this.session;
+        let saved = this.run(slf.py(), |dev| session.enter(dev))?;
+        *this.saved.lock().unwrap_or_else(PoisonError::into_inner) = saved;
+        Ok(slf)
+    }
+
+    /// Switch the output off and restore the previous settings if configured,
+    /// also if the `with` block was left due to an exception.
+    ///
+    /// Returns:
+    ///     `False`, exceptions are never suppressed.
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        let saved = self
+            .saved
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        self.run(py, |dev| Session::exit(dev, saved))?;
+        Ok(false)
     }
 
     /// List all available and compatible power supplies.
@@ -106,34 +555,36 @@ impl PowerSupply {
     ///
     /// Returns:
     ///     Response from executing the command.
-    fn execute(&mut self, command: &str) -> PyResult<Vec<u8>> {
-        Ok(self
-            .inner
-            .run_command(command)
-            .map_err(Into::<Ka3005pError>::into)?)
+    fn execute(&self, py: Python<'_>, command: &str) -> PyResult<Vec<u8>> {
+        self.run(py, |dev| dev.run_command(command))
     }
 
-    /// Get the output current setting of the power supply.
+    /// Query the identification of the power supply.
+    ///
+    /// Returns:
+    ///     The `*IDN?` response, e.g. "KORAD KA3005P V5.8 SN:03379314".
+    fn identify(&self, py: Python<'_>) -> PyResult<String> {
+        self.run(py, Ka3005p::identify)
+    }
+
+    /// Get the measured output current of the power supply, see `set_current` for the current limit.
     #[getter]
-    fn get_current(&mut self) -> PyResult<f32> {
-        let status = self._status()?;
-        Ok(status.current)
+    fn get_current(&self, py: Python<'_>) -> PyResult<f32> {
+        self.run(py, Ka3005p::read_measured_current)
     }
 
     /// Set the output current of the power supply.
     /// Args:
     ///     i: ampere's to be set.
     #[setter]
-    fn set_current(&mut self, i: f32) -> PyResult<()> {
-        let command = Command::Current(i);
-        self._execute(command)
+    fn set_current(&self, py: Python<'_>, i: f32) -> PyResult<()> {
+        self._execute(py, Command::Current(i))
     }
 
-    /// Get the output voltage setting of the power supply.
+    /// Get the measured output voltage of the power supply, see `set_voltage` for the target voltage.
     #[getter]
-    fn get_voltage(&mut self) -> PyResult<f32> {
-        let status = self._status()?;
-        Ok(status.voltage)
+    fn get_voltage(&self, py: Python<'_>) -> PyResult<f32> {
+        self.run(py, Ka3005p::read_measured_voltage)
     }
 
     /// Set the output voltage of the power supply.
@@ -141,40 +592,52 @@ impl PowerSupply {
     /// Args:
     ///     v: volt's to be set.
     #[setter]
-    fn set_voltage(&mut self, v: f32) -> PyResult<()> {
-        let command = Command::Voltage(v);
-        self._execute(command)
+    fn set_voltage(&self, py: Python<'_>, v: f32) -> PyResult<()> {
+        self._execute(py, Command::Voltage(v))
     }
 
-    /// Get the status information of the power supply.
+    /// Get the target voltage of the power supply.
     #[getter]
-    fn get_status(&mut self) -> PyResult<String> {
-        let status = self._status()?;
-        Ok(format!("{}", status))
+    fn get_set_voltage(&self, py: Python<'_>) -> PyResult<f32> {
+        self.run(py, Ka3005p::read_set_voltage)
+    }
+
+    /// Get the current limit of the power supply.
+    #[getter]
+    fn get_set_current(&self, py: Python<'_>) -> PyResult<f32> {
+        self.run(py, Ka3005p::read_set_current)
+    }
+
+    /// Get the status information of the power supply, `str()` gives a human readable summary.
+    #[getter]
+    fn get_status(&self, py: Python<'_>) -> PyResult<PyStatus> {
+        Ok(self.run(py, Ka3005p::status)?.into())
+    }
+
+    /// Get the status flags of the power supply.
+    #[getter]
+    fn get_flags(&self, py: Python<'_>) -> PyResult<PyFlags> {
+        Ok(self._flags(py)?.into())
     }
 
     /// Enable the output of the the power supply.
-    fn enable(&mut self) -> PyResult<()> {
-        let command = Command::Power(Switch::On);
-        self._execute(command)
+    fn enable(&self, py: Python<'_>) -> PyResult<()> {
+        self._execute(py, Command::Power(Switch::On))
     }
 
     /// Disable the output of the the power supply.
-    fn disable(&mut self) -> PyResult<()> {
-        let command = Command::Power(Switch::Off);
-        self._execute(command)
+    fn disable(&self, py: Python<'_>) -> PyResult<()> {
+        self._execute(py, Command::Power(Switch::Off))
     }
 
     /// Get the power supply's off/on state.
-    fn is_off(&mut self) -> PyResult<bool> {
-        let status = self._status()?;
-        Ok(!Into::<bool>::into(status.flags.output))
+    fn is_off(&self, py: Python<'_>) -> PyResult<bool> {
+        Ok(!Into::<bool>::into(self._flags(py)?.output))
     }
 
     /// Get the power supply's on/off state.
-    fn is_on(&mut self) -> PyResult<bool> {
-        let status = self._status()?;
-        Ok(status.flags.output.into())
+    fn is_on(&self, py: Python<'_>) -> PyResult<bool> {
+        Ok(self._flags(py)?.output.into())
     }
 
     /// Is beeping enabled.
@@ -182,34 +645,32 @@ impl PowerSupply {
     /// Returns:
     ///     `True` if beeping is enabled, otherwise `False`.
     #[getter]
-    fn get_beep(&mut self) -> PyResult<bool> {
-        let status = self._status()?;
-        Ok(status.flags.beep.into())
+    fn get_beep(&self, py: Python<'_>) -> PyResult<bool> {
+        Ok(self._flags(py)?.beep.into())
     }
 
     /// Set the beep state of the power supply.
     #[setter]
-    fn set_beep(&mut self, enable: bool) -> PyResult<()> {
-        let command = Command::Beep(Switch::from(enable));
-        self._execute(command)
+    fn set_beep(&self, py: Python<'_>, enable: bool) -> PyResult<()> {
+        self._execute(py, Command::Beep(Switch::from(enable)))
     }
 
     /// Save the current settings/configuration of the power supply.
     ///
     /// Args:
     ///     id: Memory slot to save to (M: 1-5).
-    fn save(&mut self, id: u32) -> PyResult<()> {
-        let command = Command::Save(id);
-        self._execute(command)
+    fn save(&self, py: Python<'_>, id: u32) -> PyResult<()> {
+        let command = Command::Save(id.try_into().map_err(Into::<Ka3005pError>::into)?);
+        self._execute(py, command)
     }
 
     /// Load stored settings/configuration to the power supply.
     ///
     /// Args:
-    ///     id: Memory slot to load from (M: -5).
-    fn load(&mut self, id: u32) -> PyResult<()> {
-        let command = Command::Load(id);
-        self._execute(command)
+    ///     id: Memory slot to load from (M: 1-5).
+    fn load(&self, py: Python<'_>, id: u32) -> PyResult<()> {
+        let command = Command::Load(id.try_into().map_err(Into::<Ka3005pError>::into)?);
+        self._execute(py, command)
     }
 
     /// Set the over current protection state of the power supply.
@@ -217,9 +678,8 @@ impl PowerSupply {
     /// Args:
     ///     enable: ocp if `True`, otherwise disable ocp.
     #[setter]
-    fn set_ocp(&mut self, enable: bool) -> PyResult<()> {
-        let command = Command::Ocp(Switch::from(enable));
-        self._execute(command)
+    fn set_ocp(&self, py: Python<'_>, enable: bool) -> PyResult<()> {
+        self._execute(py, Command::Ocp(Switch::from(enable)))
     }
 
     /// Set the over voltage protection state of the power supply.
@@ -227,9 +687,373 @@ impl PowerSupply {
     /// Args:
     ///     enable: ovp if `True`, otherwise disable ovp.
     #[setter]
-    fn set_ovp(&mut self, enable: bool) -> PyResult<()> {
-        let command = Command::Ovp(Switch::from(enable));
-        self._execute(command)
+    fn set_ovp(&self, py: Python<'_>, enable: bool) -> PyResult<()> {
+        self._execute(py, Command::Ovp(Switch::from(enable)))
+    }
+
+    /// Sample the measured voltage, current and mode at a fixed interval, requires numpy.
+    ///
+    /// The samples are taken at multiples of `interval` after the start. If the power supply
+    /// is too slow for the interval, samples are skipped.
+    ///
+    /// Args:
+    ///     duration: Seconds to acquire.
+    ///     interval: Seconds between two samples.
+    ///     callback: Called with every `Sample`, the acquisition stops if it returns `False`.
+    ///         It may use the power supply, e.g. to switch the output off.
+    ///     dataframe: Return a pandas DataFrame instead of a dict of numpy arrays.
+    ///
+    /// Returns:
+    ///     The columns "time" (seconds), "voltage", "current" and "mode" ("CV" or "CC").
+    #[pyo3(signature = (duration, interval, callback=None, *, dataframe=false))]
+    fn acquire<'py>(
+        &self,
+        py: Python<'py>,
+        duration: f64,
+        interval: f64,
+        callback: Option<Py<PyAny>>,
+        dataframe: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (duration, interval) = (seconds(duration)?, seconds(interval)?);
+        let numpy = py.import("numpy")?;
+        let pandas = dataframe.then(|| py.import("pandas")).transpose()?;
+        let samples = py.detach(|| -> PyResult<Vec<Sample>> {
+            let mut schedule = Schedule::new(duration, interval);
+            let mut samples = Vec::new();
+            while let Some(time) = schedule.wait() {
+                // Only lock for the sample, so the callback can use the power supply too
+                let sample = lock(&self.device).sample(time);
+                schedule.done(sample.is_err());
+                let sample = sample.map_err(Into::<Ka3005pError>::into)?;
+                samples.push(sample);
+                if let Some(callback) = &callback {
+                    let proceed = Python::attach(|py| -> PyResult<bool> {
+                        let result = callback.call1(py, (PySample::from(sample),))?;
+                        Ok(!matches!(result.extract::<bool>(py), Ok(false)))
+                    })?;
+                    if !proceed {
+                        break;
+                    }
+                }
+            }
+            Ok(samples)
+        })?;
+        let column = |values: Vec<f32>| numpy.call_method1("array", (values, "float32"));
+        let columns = PyDict::new(py);
+        let times: Vec<f64> = samples.iter().map(|s| s.time.as_secs_f64()).collect();
+        columns.set_item("time", numpy.call_method1("array", (times,))?)?;
+        columns.set_item(
+            "voltage",
+            column(samples.iter().map(|s| s.voltage).collect())?,
+        )?;
+        columns.set_item(
+            "current",
+            column(samples.iter().map(|s| s.current).collect())?,
+        )?;
+        let modes: Vec<&str> = samples
+            .iter()
+            .map(|s| match s.mode {
+                Mode::Cc => "CC",
+                Mode::Cv => "CV",
+            })
+            .collect();
+        columns.set_item("mode", numpy.call_method1("array", (modes,))?)?;
+        match pandas {
+            Some(pandas) => pandas.call_method1("DataFrame", (columns,)),
+            None => Ok(columns.into_any()),
+        }
+    }
+}
+
+/// Resolve an asyncio future with the outcome of an async call, unless it was cancelled meanwhile.
+#[pyfunction]
+fn complete(future: &Bound<'_, PyAny>, ok: bool, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    if future.call_method0("done")?.is_truthy()? {
+        return Ok(());
+    }
+    let method = if ok { "set_result" } else { "set_exception" };
+    future.call_method1(method, (value,))?;
+    Ok(())
+}
+
+#[pyclass(frozen)]
+/// Represents a power supply device, for use with asyncio.
+///
+/// Every call returns an awaitable. The calls are run one after the other on a worker thread,
+/// in the order they were made, e.g. a voltage set before enabling the output is applied first.
+/// Note: Creating the object blocks while the power supply is identified.
+struct AsyncPowerSupply {
+    /// Queue of the worker thread, which stops once the object is dropped
+    jobs: mpsc::Sender<Job>,
+    session: Session,
+    /// Settings active before entering the `async with` block
+    saved: Arc<Mutex<Option<(f32, f32)>>>,
+}
+
+/// An operation queued for the worker thread of an [`AsyncPowerSupply`].
+type Job = Box<dyn FnOnce(&mut Ka3005p) + Send>;
+
+/// Start a thread which runs the queued operations on the power supply in order.
+fn worker(device: Device) -> mpsc::Sender<Job> {
+    let (jobs, queue) = mpsc::channel::<Job>();
+    thread::spawn(move || {
+        for job in queue {
+            job(&mut lock(&device));
+        }
+    });
+    jobs
+}
+
+// helper methods
+impl AsyncPowerSupply {
+    /// Queue an operation on the power supply for the worker thread.
+    ///
+    /// Returns:
+    ///     An asyncio future of the running event loop which resolves to the result of the operation.
+    fn spawn<'py, T>(
+        &self,
+        py: Python<'py>,
+        operation: impl FnOnce(&mut Ka3005p) -> anyhow::Result<T> + Send + 'static,
+    ) -> PyResult<Bound<'py, PyAny>>
+    where
+        T: for<'a> IntoPyObject<'a> + Send + 'static,
+    {
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        let future = event_loop.call_method0("create_future")?;
+        let (event_loop, result) = (event_loop.unbind(), future.clone().unbind());
+        let job: Job = Box::new(move |dev| {
+            let outcome = operation(dev);
+            Python::attach(|py| {
+                let resolve = || -> PyResult<()> {
+                    let (ok, value) = match outcome.map_err(|e| PyErr::from(Ka3005pError(e))) {
+                        Ok(value) => (true, value.into_py_any(py)?),
+                        Err(e) => (false, e.into_value(py).into_any()),
+                    };
+                    let complete = wrap_pyfunction!(complete, py)?;
+                    event_loop.call_method1(
+                        py,
+                        "call_soon_threadsafe",
+                        (complete, result, ok, value),
+                    )?;
+                    Ok(())
+                };
+                // Fails if the event loop was closed in the meantime
+                if let Err(e) = resolve() {
+                    e.write_unraisable(py, None);
+                }
+            })
+        });
+        self.jobs
+            .send(job)
+            .map_err(|_| PyException::new_err("The worker thread of the power supply stopped"))?;
+        Ok(future)
+    }
+
+    fn _execute<'py>(&self, py: Python<'py>, command: Command) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, move |dev| dev.execute(command))
+    }
+}
+
+#[pymethods]
+impl AsyncPowerSupply {
+    #[new]
+    #[pyo3(signature = (serial_port=None, *, baud_rate=9600, timeout=0.06, command_delay=0.0, terminator="", retries=0, reconnect=false, model=None, slew_voltage=None, slew_current=None, voltage=None, current=None, restore=false))]
+    /// Initialize a new AsyncPowerSupply instance, takes the same arguments as PowerSupply.
+    ///
+    /// Returns:
+    ///     New instance of AsyncPowerSupply.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        serial_port: Option<&str>,
+        baud_rate: u32,
+        timeout: f64,
+        command_delay: f64,
+        terminator: &str,
+        retries: u32,
+        reconnect: bool,
+        model: Option<&str>,
+        slew_voltage: Option<f32>,
+        slew_current: Option<f32>,
+        voltage: Option<f32>,
+        current: Option<f32>,
+        restore: bool,
+    ) -> PyResult<Self> {
+        let slew_rate = slew_rate(slew_voltage, slew_current)?;
+        let device = py.detach(|| {
+            open(
+                serial_port,
+                baud_rate,
+                timeout,
+                command_delay,
+                terminator,
+                retries,
+                reconnect,
+                model,
+                slew_rate,
+            )
+        })?;
+        Ok(AsyncPowerSupply {
+            jobs: worker(device),
+            session: Session {
+                voltage,
+                current,
+                restore,
+            },
+            saved: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Apply the initial voltage and current limit, if configured.
+    ///
+    /// Returns:
+    ///     Awaitable which resolves to the power supply.
+    fn __aenter__(slf: Bound<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let this = slf.get();
+        let (session, saved) = (this.session, this.saved.clone());
+        let power_supply = slf.clone().unbind();
+        this.spawn(slf.py(), move |dev| {
+            *saved.lock().unwrap_or_else(PoisonError::into_inner) = session.enter(dev)?;
+            Ok(power_supply)
+        })
+    }
+
+    /// Switch the output off and restore the previous settings if configured,
+    /// also if the `async with` block was left due to an exception.
+    ///
+    /// Returns:
+    ///     Awaitable which resolves to `False`, exceptions are never suppressed.
+    fn __aexit__<'py>(
+        &self,
+        py: Python<'py>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let saved = self.saved.clone();
+        self.spawn(py, move |dev| {
+            let saved = saved.lock().unwrap_or_else(PoisonError::into_inner).take();
+            Session::exit(dev, saved)?;
+            Ok(false)
+        })
+    }
+
+    /// Execute a raw command on the power supply.
+    ///
+    /// Args:
+    ///     command: Raw command string.
+    ///
+    /// Returns:
+    ///     Awaitable which resolves to the response.
+    fn execute<'py>(&self, py: Python<'py>, command: String) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, move |dev| dev.run_command(&command))
+    }
+
+    /// Query the identification of the power supply.
+    ///
+    /// Returns:
+    ///     Awaitable which resolves to the `*IDN?` response.
+    fn identify<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, Ka3005p::identify)
+    }
+
+    /// Get the measured output current of the power supply.
+    fn get_current<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, Ka3005p::read_measured_current)
+    }
+
+    /// Get the measured output voltage of the power supply.
+    fn get_voltage<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, Ka3005p::read_measured_voltage)
+    }
+
+    /// Get the target voltage of the power supply.
+    fn get_set_voltage<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, Ka3005p::read_set_voltage)
+    }
+
+    /// Get the current limit of the power supply.
+    fn get_set_current<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, Ka3005p::read_set_current)
+    }
+
+    /// Set the output current of the power supply.
+    ///
+    /// Args:
+    ///     i: ampere's to be set.
+    fn set_current<'py>(&self, py: Python<'py>, i: f32) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Current(i))
+    }
+
+    /// Set the output voltage of the power supply.
+    ///
+    /// Args:
+    ///     v: volt's to be set.
+    fn set_voltage<'py>(&self, py: Python<'py>, v: f32) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Voltage(v))
+    }
+
+    /// Get the status information of the power supply.
+    fn get_status<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, |dev| Ok(PyStatus::from(dev.status()?)))
+    }
+
+    /// Get the status flags of the power supply.
+    fn get_flags<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, |dev| Ok(PyFlags::from(dev.read_flags()?)))
+    }
+
+    /// Enable the output of the the power supply.
+    fn enable<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Power(Switch::On))
+    }
+
+    /// Disable the output of the the power supply.
+    fn disable<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Power(Switch::Off))
+    }
+
+    /// Get the power supply's on/off state.
+    fn is_on<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, |dev| Ok(bool::from(dev.read_flags()?.output)))
+    }
+
+    /// Get the power supply's off/on state.
+    fn is_off<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.spawn(py, |dev| Ok(!bool::from(dev.read_flags()?.output)))
+    }
+
+    /// Set the beep state of the power supply.
+    fn set_beep<'py>(&self, py: Python<'py>, enable: bool) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Beep(Switch::from(enable)))
+    }
+
+    /// Save the current settings/configuration of the power supply.
+    ///
+    /// Args:
+    ///     id: Memory slot to save to (M: 1-5).
+    fn save<'py>(&self, py: Python<'py>, id: u32) -> PyResult<Bound<'py, PyAny>> {
+        let command = Command::Save(id.try_into().map_err(Into::<Ka3005pError>::into)?);
+        self._execute(py, command)
+    }
+
+    /// Load stored settings/configuration to the power supply.
+    ///
+    /// Args:
+    ///     id: Memory slot to load from (M: 1-5).
+    fn load<'py>(&self, py: Python<'py>, id: u32) -> PyResult<Bound<'py, PyAny>> {
+        let command = Command::Load(id.try_into().map_err(Into::<Ka3005pError>::into)?);
+        self._execute(py, command)
+    }
+
+    /// Set the over current protection state of the power supply.
+    fn set_ocp<'py>(&self, py: Python<'py>, enable: bool) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Ocp(Switch::from(enable)))
+    }
+
+    /// Set the over voltage protection state of the power supply.
+    fn set_ovp<'py>(&self, py: Python<'py>, enable: bool) -> PyResult<Bound<'py, PyAny>> {
+        self._execute(py, Command::Ovp(Switch::from(enable)))
     }
 }
 
@@ -237,5 +1061,17 @@ impl PowerSupply {
 #[pymodule]
 fn ka3005p(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PowerSupply>()?;
+    m.add_class::<AsyncPowerSupply>()?;
+    m.add_class::<PyStatus>()?;
+    m.add_class::<PySample>()?;
+    m.add_class::<PyFlags>()?;
+    m.add_class::<PyMode>()?;
+    m.add_class::<PyLock>()?;
+    m.add_class::<PyChannel>()?;
+    m.add("Error", m.py().get_type::<Error>())?;
+    m.add("DeviceNotFound", m.py().get_type::<DeviceNotFound>())?;
+    m.add("Timeout", m.py().get_type::<Timeout>())?;
+    m.add("ProtocolError", m.py().get_type::<ProtocolError>())?;
+    m.add("LimitExceeded", m.py().get_type::<LimitExceeded>())?;
     Ok(())
 }
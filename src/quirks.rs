@@ -0,0 +1,307 @@
+//! Differences between the various models and firmware versions of the power supplies.
+//!
+//! The quirks are selected automatically from the `*IDN?` response when the power supply is opened
+//! via [`Ka3005p::builder`](crate::Ka3005p::builder), but can also be configured manually:
+//!
+//! ```no_run
+//! use ka3005p::Ka3005p;
+//! use ka3005p::quirks::Quirks;
+//!
+//! let quirks = Quirks {
+//!     response_length: Some(5),
+//!     ..Quirks::default()
+//! };
+//! let mut dev = Ka3005p::builder().quirks(quirks).open().unwrap();
+//! ```
+use crate::{Command, Error, Flags, Lock, Mode, Switch};
+
+/// Bit masks of the fields within the `STATUS?` response
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatusBits {
+    /// Set if channel 1 is in CV mode
+    pub channel1: u8,
+    /// Set if channel 2 is in CV mode
+    pub channel2: u8,
+    /// Set if beeping is enabled
+    pub beep: u8,
+    /// Set if the front panel is locked
+    pub lock: u8,
+    /// Set if the output is enabled
+    pub output: u8,
+}
+
+impl Default for StatusBits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl StatusBits {
+    /// Layout used by most models
+    pub const DEFAULT: StatusBits = StatusBits {
+        channel1: 0x01,
+        channel2: 0x02,
+        beep: 0x10,
+        lock: 0x20,
+        output: 0x40,
+    };
+
+    /// Decode the raw `STATUS?` byte.
+    pub fn decode(&self, flags: u8) -> Flags {
+        let set = |mask: u8| flags & mask != 0;
+        Flags {
+            flags,
+            channel1: if set(self.channel1) {
+                Mode::Cv
+            } else {
+                Mode::Cc
+            },
+            channel2: if set(self.channel2) {
+                Mode::Cv
+            } else {
+                Mode::Cc
+            },
+            beep: Switch::from(set(self.beep)),
+            lock: if set(self.lock) {
+                Lock::Locked
+            } else {
+                Lock::Unlocked
+            },
+            output: Switch::from(set(self.output)),
+        }
+    }
+}
+
+/// Describes how commands need to be formatted and responses parsed for a specific model.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    /// Number of decimal places accepted by `VSET1`
+    pub voltage_decimals: usize,
+    /// Number of decimal places accepted by `ISET1`
+    pub current_decimals: usize,
+    /// Number of valid characters in numeric responses, some firmwares append garbage
+    pub response_length: Option<usize>,
+    /// Layout of the `STATUS?` response
+    pub status_bits: StatusBits,
+    /// Number of memory slots (M1 - Mn)
+    pub memory_slots: u8,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Known models, matched against the `*IDN?` response (e.g. `KORAD KA3005P V5.8 SN:03379314`
+/// or `TENMA72-2550V2.0`). Models which aren't listed use the default quirks.
+const KNOWN_MODELS: &[(&str, Quirks)] = &[
+    // The original, the defaults follow its protocol
+    ("KA3005P", Quirks::DEFAULT),
+    // Same protocol and STATUS? layout as the KA3005P
+    ("KD3005P", Quirks::DEFAULT),
+    // RS PRO rebrands of the KA3005P (30V/5A) and its 60V variant
+    ("RS3005P", Quirks::DEFAULT),
+    ("RS6005P", Quirks::DEFAULT),
+    // Tenma rebrands, the 72-2535 is the 3A variant
+    ("72-2540", Quirks::DEFAULT),
+    ("72-2535", Quirks::DEFAULT),
+    // Replies to numeric queries carry an additional trailing character
+    (
+        "72-2550",
+        Quirks {
+            response_length: Some(5),
+            ..Quirks::DEFAULT
+        },
+    ),
+];
+
+impl Quirks {
+    /// Quirks of the original Korad KA3005P, which most clones follow
+    pub const DEFAULT: Quirks = Quirks {
+        voltage_decimals: 2,
+        current_decimals: 3,
+        response_length: None,
+        status_bits: StatusBits::DEFAULT,
+        memory_slots: 5,
+    };
+
+    /// Select the quirks for a model based on its identification (`*IDN?` response or model name).
+    /// Unknown models use the default quirks.
+    pub fn for_identity(identity: &str) -> Self {
+        let identity = identity.to_uppercase();
+        KNOWN_MODELS
+            .iter()
+            .find(|(model, _)| identity.contains(model))
+            .map(|&(_, quirks)| quirks)
+            .unwrap_or_default()
+    }
+
+    /// Format a command as expected by the power supply.
+    pub fn format(&self, command: Command) -> String {
+        match command {
+            Command::Power(s) => match s {
+                Switch::On => String::from("OUT1"),
+                Switch::Off => String::from("OUT0"),
+            },
+            Command::Ovp(s) => match s {
+                Switch::On => String::from("OVP1"),
+                Switch::Off => String::from("OVP0"),
+            },
+            Command::Ocp(s) => match s {
+                Switch::On => String::from("OCP1"),
+                Switch::Off => String::from("OCP0"),
+            },
+            Command::Beep(s) => match s {
+                Switch::On => String::from("BEEP1"),
+                Switch::Off => String::from("BEEP0"),
+            },
+            Command::Save(id) => format!("SAV{}", id),
+            Command::Load(id) => format!("RCL{}", id),
+            Command::Voltage(v) => format!("VSET1:{:.*}", self.voltage_decimals, v),
+            Command::Current(i) => format!("ISET1:{:.*}", self.current_decimals, i),
+        }
+    }
+
+    /// Extract the text of a response, dropping non printable characters and trailing garbage.
+    pub fn response_text(&self, bytes: &[u8]) -> String {
+        let text = bytes
+            .iter()
+            .filter(|&b| (32..=126).contains(b))
+            .map(|&b| b as char);
+        match self.response_length {
+            Some(length) => text.take(length).collect(),
+            None => text.collect(),
+        }
+    }
+
+    /// Parse a numeric response.
+    pub fn parse_value(&self, bytes: &[u8]) -> anyhow::Result<f32> {
+        let text = self.response_text(bytes);
+        text.trim()
+            .parse()
+            .map_err(|e| Error::Protocol(format!("Invalid response {:?}: {}", text, e)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_model() {
+        let quirks = Quirks::for_identity("TENMA 72-2550 V2.0");
+        assert_eq!(quirks.response_length, Some(5));
+        assert_eq!(quirks.parse_value(b"1.000K").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_unknown_model() {
+        assert_eq!(
+            Quirks::for_identity("ACME PSU-3005 V1.0"),
+            Quirks::default()
+        );
+    }
+
+    #[test]
+    fn test_status_of_known_models() {
+        // Identification, STATUS? response, expected channel 1 mode, beep, lock and output
+        let cases = [
+            (
+                "KORAD KA3005P V5.8 SN:03379314",
+                0x51,
+                Mode::Cv,
+                Switch::On,
+                Lock::Unlocked,
+                Switch::On,
+            ),
+            (
+                "KORADKD3005PV2.0",
+                0x50,
+                Mode::Cc,
+                Switch::On,
+                Lock::Unlocked,
+                Switch::On,
+            ),
+            (
+                "RS PRO RS3005P V1.0",
+                0x11,
+                Mode::Cv,
+                Switch::On,
+                Lock::Unlocked,
+                Switch::Off,
+            ),
+            (
+                "RS6005P V2.0",
+                0x31,
+                Mode::Cv,
+                Switch::On,
+                Lock::Locked,
+                Switch::Off,
+            ),
+            (
+                "TENMA 72-2540 V2.1",
+                0x41,
+                Mode::Cv,
+                Switch::Off,
+                Lock::Unlocked,
+                Switch::On,
+            ),
+            (
+                "TENMA 72-2535 V2.1",
+                0x61,
+                Mode::Cv,
+                Switch::Off,
+                Lock::Locked,
+                Switch::On,
+            ),
+            (
+                "TENMA72-2550V2.0",
+                0x50,
+                Mode::Cc,
+                Switch::On,
+                Lock::Unlocked,
+                Switch::On,
+            ),
+        ];
+        assert_eq!(cases.len(), KNOWN_MODELS.len());
+        for (identity, status, channel1, beep, lock, output) in cases {
+            let quirks = Quirks::for_identity(identity);
+            assert!(
+                KNOWN_MODELS
+                    .iter()
+                    .any(|(model, _)| identity.contains(model)),
+                "{}",
+                identity
+            );
+            let flags = quirks.status_bits.decode(status);
+            assert_eq!(
+                (flags.channel1, flags.beep, flags.lock, flags.output),
+                (channel1, beep, lock, output),
+                "{}",
+                identity
+            );
+        }
+    }
+
+    #[test]
+    fn test_decimals() {
+        let quirks = Quirks {
+            voltage_decimals: 3,
+            current_decimals: 2,
+            ..Quirks::default()
+        };
+        assert_eq!(quirks.format(Command::Voltage(1.2344)), "VSET1:1.234");
+        assert_eq!(quirks.format(Command::Current(1.2344)), "ISET1:1.23");
+        assert_eq!(quirks.format(Command::Power(Switch::On)), "OUT1");
+    }
+
+    #[test]
+    fn test_response_text() {
+        let quirks = Quirks::default();
+        assert_eq!(quirks.response_text(b"12.00\x00\n"), "12.00");
+        assert!(quirks.parse_value(b"").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Configuration for automatically reconnecting to a power supply after the
+//! USB connection was lost, e.g. because the supply was power cycled.
+//!
+//! ```no_run
+//! use ka3005p::reconnect::Reconnect;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! dev.set_reconnect(Some(Reconnect::default()));
+//! ```
+use serialport::{SerialPortType, UsbPortInfo};
+use std::time::Duration;
+
+/// Controls how and how often the library tries to reconnect to a power supply.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reconnect {
+    /// Number of attempts to reopen the serial port before giving up
+    pub attempts: u32,
+    /// Delay before the first attempt, it doubles with every failed attempt
+    pub backoff: Duration,
+    /// Upper limit for the delay between two attempts
+    pub max_backoff: Duration,
+    /// Apply the last voltage and current set through this library after reconnecting
+    pub restore_setpoints: bool,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect {
+            attempts: 10,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            restore_setpoints: true,
+        }
+    }
+}
+
+impl Reconnect {
+    /// The delays to wait before each attempt.
+    pub(crate) fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        (0..self.attempts).map(|attempt| {
+            self.backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(self.max_backoff)
+        })
+    }
+}
+
+/// Look up the USB identity of a serial port, so it can be found again
+/// even if the operating system assigns a different port name after a reconnect.
+pub(crate) fn usb_identity(port_name: &str) -> Option<UsbPortInfo> {
+    serialport::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|info| info.port_name == port_name)
+        .and_then(|info| match info.port_type {
+            SerialPortType::UsbPort(usb) => Some(usb),
+            _ => None,
+        })
+}
+
+/// Find the port name of a previously connected power supply.
+/// Devices with a serial number are matched by their USB identity, all others by port name.
+pub(crate) fn rediscover(port_name: &str, identity: Option<&UsbPortInfo>) -> Option<String> {
+    let ports = serialport::available_ports().ok()?;
+    let identity = match identity {
+        Some(usb) if usb.serial_number.is_some() => usb,
+        _ => {
+            return ports
+                .into_iter()
+                .find(|info| info.port_name == port_name)
+                .map(|info| info.port_name)
+        }
+    };
+    ports
+        .into_iter()
+        .find(|info| match &info.port_type {
+            SerialPortType::UsbPort(usb) => {
+                usb.vid == identity.vid
+                    && usb.pid == identity.pid
+                    && usb.serial_number == identity.serial_number
+            }
+            _ => false,
+        })
+        .map(|info| info.port_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delays() {
+        let reconnect = Reconnect {
+            attempts: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            restore_setpoints: false,
+        };
+        let delays: Vec<u128> = reconnect.delays().map(|d| d.as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+    }
+}
@@ -0,0 +1,189 @@
+//! Apply several settings at once in a safe order, see [`Ka3005p::apply`].
+//!
+//! ```no_run
+//! use ka3005p::settings::Settings;
+//! use ka3005p::Switch;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let status = dev
+//!     .apply(&Settings {
+//!         voltage: Some(3.3),
+//!         current: Some(0.2),
+//!         output: Some(Switch::On),
+//!         ..Settings::default()
+//!     })
+//!     .unwrap();
+//! println!("{}", status);
+//! ```
+use crate::{Command, Ka3005p, Status, Switch};
+use anyhow::ensure;
+
+/// Settings to apply with [`Ka3005p::apply`], `None` keeps the current setting.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Settings {
+    /// Target voltage in volts
+    pub voltage: Option<f32>,
+    /// Current limit in amps
+    pub current: Option<f32>,
+    /// Over current protection
+    pub ocp: Option<Switch>,
+    /// Over voltage protection
+    pub ovp: Option<Switch>,
+    /// Interface beep
+    pub beep: Option<Switch>,
+    /// Output, applied after all other settings
+    pub output: Option<Switch>,
+}
+
+impl Settings {
+    /// Whether settings which must not change while the output is on are included
+    fn changes_limits(&self) -> bool {
+        self.voltage.is_some() || self.current.is_some() || self.ocp.is_some() || self.ovp.is_some()
+    }
+}
+
+impl Ka3005p {
+    /// Apply `settings`, verify them by reading them back and return the resulting status.
+    ///
+    /// The output is switched off while the current, voltage and protections are changed (in this order).
+    /// Afterwards it is switched on if requested, or if it was on before. If a step fails, the output stays off.
+    /// Note: The power supply doesn't report the OCP/OVP state, so these can't be verified.
+    pub fn apply(&mut self, settings: &Settings) -> anyhow::Result<Status> {
+        let was_on = self.read_output_enable()?;
+        let mut is_on = was_on;
+        if was_on && settings.changes_limits() {
+            self.switch_output(Switch::Off)?;
+            is_on = false;
+        }
+        if let Some(current) = settings.current {
+            self.execute(Command::Current(current))?;
+            let decimals = self.quirks.current_decimals;
+            verify("current", current, self.read_set_current()?, decimals)?;
+        }
+        if let Some(voltage) = settings.voltage {
+            self.execute(Command::Voltage(voltage))?;
+            let decimals = self.quirks.voltage_decimals;
+            verify("voltage", voltage, self.read_set_voltage()?, decimals)?;
+        }
+        if let Some(ocp) = settings.ocp {
+            self.execute(Command::Ocp(ocp))?;
+        }
+        if let Some(ovp) = settings.ovp {
+            self.execute(Command::Ovp(ovp))?;
+        }
+        if let Some(beep) = settings.beep {
+            self.execute(Command::Beep(beep))?;
+            ensure!(
+                self.read_flags()?.beep == beep,
+                "The beep was not switched {}",
+                on_off(beep)
+            );
+        }
+        let output = settings.output.unwrap_or(Switch::from(was_on));
+        if bool::from(output) != is_on {
+            self.switch_output(output)?;
+        }
+        self.status()
+    }
+
+    /// Switch the output and verify the new state via the status flags.
+    pub(crate) fn switch_output(&mut self, output: Switch) -> anyhow::Result<()> {
+        self.execute(Command::Power(output))?;
+        ensure!(
+            self.read_flags()?.output == output,
+            "The output was not switched {}",
+            on_off(output)
+        );
+        Ok(())
+    }
+}
+
+/// Check that a value was applied, allowing for the rounding to the precision of the power supply.
+fn verify(name: &str, expected: f32, actual: f32, decimals: usize) -> anyhow::Result<()> {
+    let resolution = 10f32.powi(-(decimals as i32));
+    ensure!(
+        (expected - actual).abs() < resolution,
+        "The {} was not applied, set {} but read back {}",
+        name,
+        expected,
+        actual
+    );
+    Ok(())
+}
+
+fn on_off(switch: Switch) -> &'static str {
+    match switch {
+        Switch::On => "on",
+        Switch::Off => "off",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+
+    fn mock() -> MockPort {
+        let mock = MockPort::new();
+        mock.on("VOUT1?", Reply::data("3.30"))
+            .on("IOUT1?", Reply::data("0.010"))
+            .on("VSET1?", Reply::data("3.30"))
+            .on("ISET1?", Reply::data("0.200"));
+        mock
+    }
+
+    #[test]
+    fn test_apply_in_safe_order() {
+        let mock = mock();
+        // Output on, off while applying, on again
+        mock.once("STATUS?", Reply::Data(vec![0x51]))
+            .once("STATUS?", Reply::Data(vec![0x11]))
+            .on("STATUS?", Reply::Data(vec![0x51]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let status = dev
+            .apply(&Settings {
+                voltage: Some(3.3),
+                current: Some(0.2),
+                ocp: Some(Switch::On),
+                ..Settings::default()
+            })
+            .unwrap();
+        assert_eq!(status.set_voltage, 3.3);
+        assert_eq!(status.flags.output, Switch::On);
+        assert_eq!(
+            mock.requests()[..10],
+            [
+                "STATUS?",
+                "OUT0",
+                "STATUS?",
+                "ISET1:0.200",
+                "ISET1?",
+                "VSET1:3.30",
+                "VSET1?",
+                "OCP1",
+                "OUT1",
+                "STATUS?"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_stops_on_readback_mismatch() {
+        let mock = mock();
+        mock.on("STATUS?", Reply::Data(vec![0x11]));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let error = dev
+            .apply(&Settings {
+                voltage: Some(5.0),
+                output: Some(Switch::On),
+                ..Settings::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The voltage was not applied, set 5 but read back 3.3"
+        );
+        assert!(!mock.requests().contains(&"OUT1".to_string()));
+    }
+}
@@ -0,0 +1,288 @@
+//! Limit how fast the voltage and current change (soft start), e.g. for loads with large input capacitors
+//! or circuits which must not see voltage steps.
+//!
+//! ```no_run
+//! use ka3005p::slew::SlewRate;
+//! use ka3005p::{Command, Switch};
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! dev.set_slew_rate(Some("2V/s".parse().unwrap()));
+//! // Starts at 0V and rises to the voltage setpoint with 2V/s
+//! dev.execute(Command::Power(Switch::On)).unwrap();
+//! // Takes 3s from 5V
+//! dev.execute(Command::Voltage(11.0)).unwrap();
+//! ```
+use crate::{Command, Ka3005p, Switch};
+use anyhow::{anyhow, ensure, Context};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Maximum rates of change of the setpoints, see [`Ka3005p::set_slew_rate`].
+///
+/// Parsed from a comma separated list of rates, e.g. `2V/s`, `0.5A/s` or `2V/s,0.5A/s`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlewRate {
+    /// Maximum change of the voltage in volts per second, `None` changes it at once
+    pub voltage: Option<f32>,
+    /// Maximum change of the current limit in amps per second, `None` changes it at once
+    pub current: Option<f32>,
+    /// Time between two intermediate steps
+    pub interval: Duration,
+}
+
+impl Default for SlewRate {
+    fn default() -> Self {
+        SlewRate {
+            voltage: None,
+            current: None,
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A change of a setpoint, from the first to the second value
+type Change = Option<(f32, f32)>;
+
+impl SlewRate {
+    /// Intermediate (voltage, current) setpoints to go through, one every `interval`.
+    /// The last step is the target, both quantities reach it at the same time.
+    pub fn steps(&self, voltage: Change, current: Change) -> Vec<(Option<f32>, Option<f32>)> {
+        let count = |change: Change, rate: Option<f32>| -> usize {
+            let (Some((from, to)), Some(rate)) = (change, rate) else {
+                return 1;
+            };
+            let step = rate * self.interval.as_secs_f32();
+            if step <= 0.0 {
+                return 1;
+            }
+            // Allow for float noise, e.g. 1.0 / 0.2 = 5.0000005
+            ((to - from).abs() / step - 1e-4).ceil().max(1.0) as usize
+        };
+        let count = count(voltage, self.voltage).max(count(current, self.current));
+        let at = |change: Change, step: usize| {
+            change.map(|(from, to)| match step {
+                step if step == count => to,
+                step => from + (to - from) * step as f32 / count as f32,
+            })
+        };
+        (1..=count)
+            .map(|step| (at(voltage, step), at(current, step)))
+            .collect()
+    }
+}
+
+impl FromStr for SlewRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut slew_rate = SlewRate::default();
+        for rate in s.split(',') {
+            let rate = rate.trim();
+            let lower = rate.to_lowercase();
+            let (value, unit) = lower
+                .strip_suffix("/s")
+                .and_then(|value| value.char_indices().last())
+                .map(|(position, unit)| (lower[..position].trim(), unit))
+                .ok_or_else(|| anyhow!("Invalid slew rate {:?}, expected e.g. 2V/s", rate))?;
+            let value: f32 = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid slew rate {:?}, expected e.g. 2V/s", rate))?;
+            ensure!(value > 0.0, "The slew rate {:?} must be positive", rate);
+            match unit {
+                'v' => slew_rate.voltage = Some(value),
+                'a' => slew_rate.current = Some(value),
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid unit in slew rate {:?}, expected V/s or A/s",
+                        rate
+                    ))
+                }
+            }
+        }
+        Ok(slew_rate)
+    }
+}
+
+impl Ka3005p {
+    /// Execute `command`, ramping the setpoints if it changes them while the output is on.
+    pub(crate) fn slew(&mut self, slew_rate: SlewRate, command: Command) -> anyhow::Result<()> {
+        match command {
+            Command::Voltage(v) if slew_rate.voltage.is_some() && self.read_output_enable()? => {
+                let from = self.read_set_voltage()?;
+                self.ramp(slew_rate, Some((from, v)), None)
+            }
+            Command::Current(i) if slew_rate.current.is_some() && self.read_output_enable()? => {
+                let from = self.read_set_current()?;
+                self.ramp(slew_rate, None, Some((from, i)))
+            }
+            Command::Power(Switch::On) if !self.read_output_enable()? => {
+                let voltage = match slew_rate.voltage {
+                    Some(_) => Some((0.0, self.read_set_voltage()?)),
+                    None => None,
+                };
+                let current = match slew_rate.current {
+                    Some(_) => Some((0.0, self.read_set_current()?)),
+                    None => None,
+                };
+                if voltage.is_some() {
+                    self.send(Command::Voltage(0.0))?;
+                }
+                if current.is_some() {
+                    self.send(Command::Current(0.0))?;
+                }
+                self.send(command)?;
+                self.ramp(slew_rate, voltage, current)
+            }
+            _ => self.send(command),
+        }
+    }
+
+    fn ramp(
+        &mut self,
+        slew_rate: SlewRate,
+        voltage: Change,
+        current: Change,
+    ) -> anyhow::Result<()> {
+        let mut reached = (voltage.map(|(from, _)| from), current.map(|(from, _)| from));
+        let stopped = |reached: (Option<f32>, Option<f32>)| {
+            let reached: Vec<String> = [
+                reached.0.map(|v| format!("{:.2}V", v)),
+                reached.1.map(|i| format!("{:.3}A", i)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            format!("The ramp stopped at {}", reached.join(", "))
+        };
+        for (number, (v, i)) in slew_rate.steps(voltage, current).into_iter().enumerate() {
+            if number > 0 {
+                thread::sleep(slew_rate.interval);
+                if let Some(heartbeat) = &self.heartbeat {
+                    heartbeat();
+                }
+            }
+            if let Some(v) = v {
+                self.send(Command::Voltage(v))
+                    .with_context(|| stopped(reached))?;
+                reached.0 = Some(v);
+            }
+            if let Some(i) = i {
+                self.send(Command::Current(i))
+                    .with_context(|| stopped(reached))?;
+                reached.1 = Some(i);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPort, Reply};
+    use std::time::Instant;
+
+    #[test]
+    fn test_parse() {
+        let slew_rate: SlewRate = "2V/s, 0.5 A/s".parse().unwrap();
+        assert_eq!(slew_rate.voltage, Some(2.0));
+        assert_eq!(slew_rate.current, Some(0.5));
+        assert_eq!("1.5v/s".parse::<SlewRate>().unwrap().voltage, Some(1.5));
+        for invalid in ["2V", "V/s", "2W/s", "-1V/s", "0A/s", ""] {
+            assert!(invalid.parse::<SlewRate>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_soft_start() {
+        let mock = MockPort::new();
+        mock.on("STATUS?", Reply::Data(vec![0x11]))
+            .on("VSET1?", Reply::data("5.00"));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        dev.set_slew_rate(Some(SlewRate {
+            voltage: Some(20.0),
+            current: None,
+            interval: Duration::from_millis(50),
+        }));
+        dev.execute(Command::Power(Switch::On)).unwrap();
+        assert_eq!(
+            mock.requests(),
+            [
+                "STATUS?",
+                "VSET1?",
+                "VSET1:0.00",
+                "OUT1",
+                "VSET1:1.00",
+                "VSET1:2.00",
+                "VSET1:3.00",
+                "VSET1:4.00",
+                "VSET1:5.00"
+            ]
+        );
+        // Nothing to ramp while the output is off
+        dev.execute(Command::Voltage(12.0)).unwrap();
+        assert_eq!(mock.requests().last().unwrap(), "VSET1:12.00");
+    }
+
+    #[test]
+    fn test_ramp_with_output_on() {
+        let mock = MockPort::new();
+        mock.on("STATUS?", Reply::Data(vec![0x51]))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("ISET1?", Reply::data("1.000"));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let interval = Duration::from_millis(20);
+        dev.set_slew_rate(Some(SlewRate {
+            voltage: Some(10.0),
+            current: Some(2.5),
+            interval,
+        }));
+        let start = Instant::now();
+        dev.execute(Command::Voltage(6.0)).unwrap();
+        // 0.2V per step, with the interval between two steps
+        assert!(start.elapsed() >= interval * 4);
+        assert_eq!(
+            mock.requests(),
+            [
+                "STATUS?",
+                "VSET1?",
+                "VSET1:5.20",
+                "VSET1:5.40",
+                "VSET1:5.60",
+                "VSET1:5.80",
+                "VSET1:6.00"
+            ]
+        );
+        mock.clear_requests();
+        let start = Instant::now();
+        dev.execute(Command::Current(0.5)).unwrap();
+        assert!(start.elapsed() >= interval * 9);
+        let requests = mock.requests();
+        let steps: Vec<&String> = requests
+            .iter()
+            .filter(|r| r.starts_with("ISET1:"))
+            .collect();
+        // 0.05A per step
+        assert_eq!(steps.len(), 10);
+        assert_eq!(steps[0], "ISET1:0.950");
+        assert_eq!(steps[9], "ISET1:0.500");
+    }
+
+    #[test]
+    fn test_interrupted_ramp() {
+        let mock = MockPort::new();
+        mock.on("STATUS?", Reply::Data(vec![0x51]))
+            .on("VSET1?", Reply::data("5.00"))
+            .on("VSET1:5.60", Reply::Error(std::io::ErrorKind::BrokenPipe));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        dev.set_slew_rate(Some(SlewRate {
+            voltage: Some(10.0),
+            current: None,
+            interval: Duration::from_millis(20),
+        }));
+        let error = dev.execute(Command::Voltage(6.0)).unwrap_err();
+        assert_eq!(error.to_string(), "The ramp stopped at 5.40V");
+    }
+}
@@ -0,0 +1,276 @@
+//! Record the traffic of a session with a power supply and replay it without hardware,
+//! e.g. to reproduce a bug seen on the bench in a regression test.
+//!
+//! A transcript contains one line per transfer: the milliseconds since the recording started,
+//! the request and the response, separated by tabs. Non printable bytes are escaped.
+//!
+//! ```no_run
+//! use ka3005p::transcript::{Recorder, ReplayPort};
+//! use ka3005p::Ka3005p;
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! dev.set_recorder(Some(Recorder::create("session.txt").unwrap()));
+//! dev.read_set_voltage().unwrap();
+//! drop(dev);
+//!
+//! let replay = ReplayPort::open("session.txt").unwrap();
+//! let mut dev = Ka3005p::new_from_serial(Box::new(replay)).unwrap();
+//! dev.read_set_voltage().unwrap();
+//! ```
+use crate::protocol::{escape, unescape};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// A single transfer of a transcript
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    /// Time since the recording started
+    pub elapsed: Duration,
+    /// Raw bytes sent to the power supply, including the terminator
+    pub request: Vec<u8>,
+    /// Raw bytes received from the power supply
+    pub response: Vec<u8>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            self.elapsed.as_millis(),
+            escape(&self.request),
+            escape(&self.response)
+        )
+    }
+}
+
+impl FromStr for Entry {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split('\t').collect();
+        anyhow::ensure!(
+            fields.len() == 3,
+            "Expected 3 tab separated fields: {:?}",
+            s
+        );
+        Ok(Entry {
+            elapsed: Duration::from_millis(fields[0].parse()?),
+            request: unescape(fields[1])?,
+            response: unescape(fields[2])?,
+        })
+    }
+}
+
+/// Read a transcript, empty lines and lines starting with `#` are ignored.
+pub fn read_transcript(reader: impl BufRead) -> anyhow::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = line
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid transcript line {}: {:#}", number + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Writes the transfers of a power supply to a transcript, see [`Ka3005p::set_recorder`](crate::Ka3005p::set_recorder).
+pub struct Recorder {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Record to any writer, the timestamps are relative to the creation of the recorder.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Recorder {
+            writer: Box::new(writer),
+            start: Instant::now(),
+        }
+    }
+
+    /// Record to a file, an existing file is overwritten.
+    pub fn create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Recorder::new(io::LineWriter::new(file)))
+    }
+
+    /// Append a transfer to the transcript.
+    pub fn record(&mut self, request: &[u8], response: &[u8]) -> io::Result<()> {
+        let entry = Entry {
+            elapsed: self.start.elapsed(),
+            request: request.to_vec(),
+            response: response.to_vec(),
+        };
+        writeln!(self.writer, "{}", entry)
+    }
+}
+
+/// A serial port which answers with the responses of a transcript.
+///
+/// The requests need to match the transcript exactly, otherwise writing fails.
+/// The recorded timing is not reproduced, responses are available immediately.
+pub struct ReplayPort {
+    entries: VecDeque<Entry>,
+    written: Vec<u8>,
+    pending: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl ReplayPort {
+    /// Replay the given transfers.
+    pub fn new(entries: Vec<Entry>) -> Self {
+        ReplayPort {
+            entries: entries.into(),
+            written: Vec::new(),
+            pending: VecDeque::new(),
+            timeout: Duration::ZERO,
+        }
+    }
+
+    /// Replay a transcript file.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(ReplayPort::new(read_transcript(io::BufReader::new(file))?))
+    }
+
+    /// Number of transfers which were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn bytes_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Error for a request which doesn't match the transcript, the request is discarded.
+    fn unexpected(&mut self) -> io::Error {
+        let message = match self.entries.front() {
+            Some(entry) => format!(
+                "Unexpected request \"{}\", expected \"{}\"",
+                escape(&self.written),
+                escape(&entry.request)
+            ),
+            None => format!(
+                "Unexpected request \"{}\", the transcript is exhausted",
+                escape(&self.written)
+            ),
+        };
+        self.written.clear();
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+}
+
+impl io::Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend(buf);
+        while let Some(entry) = self.entries.front() {
+            let length = entry.request.len().min(self.written.len());
+            if entry.request[..length] != self.written[..length] {
+                return Err(self.unexpected());
+            }
+            if length < entry.request.len() {
+                break;
+            }
+            self.written.drain(..length);
+            self.pending.extend(&entry.response);
+            self.entries.pop_front();
+        }
+        if !self.written.is_empty() && self.entries.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "No response"));
+        }
+        let count = buf.len().min(self.pending.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..count)) {
+            *dst = src;
+        }
+        Ok(count)
+    }
+}
+
+crate::virtual_port::impl_serial_port!(ReplayPort);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Command, Ka3005p, Switch};
+
+    const TRANSCRIPT: &str = "# recorded on the bench
+0\tVSET1?\t5.00
+12\tOUT1\t
+80\tSTATUS?\t\\x51
+";
+
+    #[test]
+    fn test_entry_round_trip() {
+        let entries = read_transcript(TRANSCRIPT.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].response, vec![0x51]);
+        for entry in entries {
+            assert_eq!(entry.to_string().parse::<Entry>().unwrap(), entry);
+        }
+        assert!(read_transcript("1\tOUT1".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let replay = ReplayPort::new(read_transcript(TRANSCRIPT.as_bytes()).unwrap());
+        let mut dev = Ka3005p::new_from_serial(Box::new(replay)).unwrap();
+        assert_eq!(dev.read_set_voltage().unwrap(), 5.0);
+        dev.execute(Command::Power(Switch::On)).unwrap();
+        assert!(dev.read_output_enable().unwrap());
+        assert!(dev.execute(Command::Power(Switch::Off)).is_err());
+    }
+
+    #[test]
+    fn test_unexpected_request() {
+        let replay = ReplayPort::new(read_transcript(TRANSCRIPT.as_bytes()).unwrap());
+        let mut dev = Ka3005p::new_from_serial(Box::new(replay)).unwrap();
+        let error = dev.read_set_current().unwrap_err();
+        assert!(format!("{:#}", error).contains("expected \"VSET1?\""));
+    }
+
+    #[test]
+    fn test_record() {
+        // Unique per process, so concurrent test runs don't share the file
+        let path =
+            std::env::temp_dir().join(format!("ka3005p-{}-test_record.txt", std::process::id()));
+        let replay = ReplayPort::new(read_transcript(TRANSCRIPT.as_bytes()).unwrap());
+        let mut dev = Ka3005p::new_from_serial(Box::new(replay)).unwrap();
+        dev.set_recorder(Some(Recorder::create(&path).unwrap()));
+        dev.read_set_voltage().unwrap();
+        dev.execute(Command::Power(Switch::On)).unwrap();
+        dev.read_flags().unwrap();
+        drop(dev);
+
+        let recorded = ReplayPort::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = read_transcript(TRANSCRIPT.as_bytes()).unwrap();
+        let strip_time = |entries: Vec<Entry>| -> Vec<(Vec<u8>, Vec<u8>)> {
+            entries
+                .into_iter()
+                .map(|e| (e.request, e.response))
+                .collect()
+        };
+        assert_eq!(strip_time(recorded.entries.into()), strip_time(expected));
+    }
+}
@@ -0,0 +1,95 @@
+//! Helpers for serial ports which don't talk to real hardware.
+
+/// Implement [`serialport::SerialPort`] for a port which only exchanges data via [`std::io::Read`]
+/// and [`std::io::Write`]. The type needs a `timeout: Duration` field and a `bytes_pending(&self) -> usize` method.
+/// Optionally a function implementing `try_clone` can be passed, by default the port can't be cloned.
+macro_rules! impl_serial_port {
+    ($port:ty) => {
+        $crate::virtual_port::impl_serial_port!($port, |_: &$port| Err(serialport::Error::new(
+            serialport::ErrorKind::Unknown,
+            "A virtual port can not be cloned",
+        )));
+    };
+    ($port:ty, $try_clone:expr) => {
+        impl serialport::SerialPort for $port {
+            fn name(&self) -> Option<String> {
+                None
+            }
+            fn baud_rate(&self) -> serialport::Result<u32> {
+                Ok(9600)
+            }
+            fn data_bits(&self) -> serialport::Result<serialport::DataBits> {
+                Ok(serialport::DataBits::Eight)
+            }
+            fn flow_control(&self) -> serialport::Result<serialport::FlowControl> {
+                Ok(serialport::FlowControl::None)
+            }
+            fn parity(&self) -> serialport::Result<serialport::Parity> {
+                Ok(serialport::Parity::None)
+            }
+            fn stop_bits(&self) -> serialport::Result<serialport::StopBits> {
+                Ok(serialport::StopBits::One)
+            }
+            fn timeout(&self) -> std::time::Duration {
+                self.timeout
+            }
+            fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn set_data_bits(&mut self, _: serialport::DataBits) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn set_flow_control(&mut self, _: serialport::FlowControl) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn set_parity(&mut self, _: serialport::Parity) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn set_stop_bits(&mut self, _: serialport::StopBits) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn set_timeout(&mut self, timeout: std::time::Duration) -> serialport::Result<()> {
+                self.timeout = timeout;
+                Ok(())
+            }
+            fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+            fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+            fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+                Ok(false)
+            }
+            fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+            fn bytes_to_read(&self) -> serialport::Result<u32> {
+                Ok(self.bytes_pending() as u32)
+            }
+            fn bytes_to_write(&self) -> serialport::Result<u32> {
+                Ok(0)
+            }
+            fn clear(&self, _: serialport::ClearBuffer) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn try_clone(&self) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+                $try_clone(self)
+            }
+            fn set_break(&self) -> serialport::Result<()> {
+                Ok(())
+            }
+            fn clear_break(&self) -> serialport::Result<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use impl_serial_port;
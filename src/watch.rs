@@ -0,0 +1,328 @@
+//! Detect state changes of the power supply by comparing successive [`Status`] snapshots.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::watch::{Quantity, Watcher};
+//!
+//! let mut dev = ka3005p::find_serial_port().unwrap();
+//! let mut watcher = Watcher::new().threshold(Quantity::Current, 0.5);
+//! watcher.on_event(|event| println!("{}", event));
+//! watcher.run(&mut dev, Duration::from_millis(500)).unwrap();
+//! ```
+use crate::{Channel, Ka3005p, Lock, Mode, Status, Switch};
+use std::fmt;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// A measured quantity of the power supply
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quantity {
+    /// Measured output voltage in volts
+    Voltage,
+    /// Measured output current in amps
+    Current,
+    /// Output power in watts, calculated from the measured voltage and current
+    Power,
+}
+
+impl Quantity {
+    pub(crate) fn of(self, status: &Status) -> f32 {
+        match self {
+            Quantity::Voltage => status.voltage,
+            Quantity::Current => status.current,
+            Quantity::Power => status.voltage * status.current,
+        }
+    }
+}
+
+/// Direction in which a threshold was crossed
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// The value is now at or above the threshold
+    Rising,
+    /// The value is now below the threshold
+    Falling,
+}
+
+/// State changes detected by the [`Watcher`]
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// A channel switched between CC and CV mode
+    ModeChanged {
+        /// Channel which changed its mode
+        channel: Channel,
+        /// The new mode
+        mode: Mode,
+    },
+    /// The output has been switched on or off
+    OutputChanged(Switch),
+    /// The output has been switched off although this wasn't announced via [`Watcher::expect_output_off`],
+    /// e.g. by a protection trip, the front panel or another program.
+    UnexpectedOutputOff,
+    /// The front panel has been locked or unlocked
+    LockChanged(Lock),
+    /// Beeping has been enabled or disabled
+    BeepChanged(Switch),
+    /// The connection to the power supply was lost and has been reestablished,
+    /// see [`Ka3005p::set_reconnect`]
+    Reconnected,
+    /// A measured value crossed a registered threshold
+    ThresholdCrossed {
+        /// The quantity which crossed the threshold
+        quantity: Quantity,
+        /// The registered threshold
+        threshold: f32,
+        /// The measured value
+        value: f32,
+        /// Direction of the crossing
+        direction: Direction,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::ModeChanged { channel, mode } => {
+                write!(f, "Mode changed, Channel: {:?}, Mode: {:?}", channel, mode)
+            }
+            Event::OutputChanged(output) => write!(f, "Output changed, Output: {:?}", output),
+            Event::UnexpectedOutputOff => write!(f, "Output switched off unexpectedly"),
+            Event::LockChanged(lock) => write!(f, "Lock changed, Lock: {:?}", lock),
+            Event::BeepChanged(beep) => write!(f, "Beep changed, Beep: {:?}", beep),
+            Event::Reconnected => write!(f, "Reconnected to the power supply"),
+            Event::ThresholdCrossed {
+                quantity,
+                threshold,
+                value,
+                direction,
+            } => write!(
+                f,
+                "Threshold crossed, {:?}: {:5.3} ({:?} {:5.3})",
+                quantity, value, direction, threshold
+            ),
+        }
+    }
+}
+
+type Callback = Box<dyn FnMut(&Event) + Send>;
+
+/// Polls the power supply and reports changes to registered callbacks and channels.
+#[derive(Default)]
+pub struct Watcher {
+    previous: Option<Status>,
+    thresholds: Vec<(Quantity, f32)>,
+    callbacks: Vec<Callback>,
+    senders: Vec<mpsc::Sender<Event>>,
+    expect_off: bool,
+    reconnects: Option<u32>,
+}
+
+impl Watcher {
+    /// Create a watcher without any thresholds or subscribers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report an event whenever the measured `quantity` crosses `level`.
+    pub fn threshold(mut self, quantity: Quantity, level: f32) -> Self {
+        self.thresholds.push((quantity, level));
+        self
+    }
+
+    /// Register a callback which will be invoked for every event.
+    pub fn on_event(&mut self, callback: impl FnMut(&Event) + Send + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Create a channel which will receive every event.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Announce that the output will be switched off on purpose,
+    /// so the next off transition won't be reported as [`Event::UnexpectedOutputOff`].
+    pub fn expect_output_off(&mut self) {
+        self.expect_off = true;
+    }
+
+    /// Query the status of the power supply and report all changes since the last poll.
+    pub fn poll(&mut self, dev: &mut Ka3005p) -> anyhow::Result<Vec<Event>> {
+        let status = dev.status()?;
+        let mut events = Vec::new();
+        if let Some(previous) = self.reconnects.replace(dev.reconnects()) {
+            if previous != dev.reconnects() {
+                events.push(Event::Reconnected);
+            }
+        }
+        events.extend(self.compare(status));
+        self.dispatch(&events);
+        Ok(events)
+    }
+
+    /// Poll the power supply every `interval`, this only returns on communication errors.
+    pub fn run(&mut self, dev: &mut Ka3005p, interval: Duration) -> anyhow::Result<()> {
+        loop {
+            self.poll(dev)?;
+            thread::sleep(interval);
+        }
+    }
+
+    /// Compare the `status` against the previous snapshot and report all changes.
+    /// The first snapshot only establishes the baseline and never produces events.
+    pub fn update(&mut self, status: Status) -> Vec<Event> {
+        let events = self.compare(status);
+        self.dispatch(&events);
+        events
+    }
+
+    fn compare(&mut self, status: Status) -> Vec<Event> {
+        let events = match self.previous.take() {
+            Some(previous) => self.diff(&previous, &status),
+            None => Vec::new(),
+        };
+        self.previous = Some(status);
+        events
+    }
+
+    fn dispatch(&mut self, events: &[Event]) {
+        for event in events {
+            for callback in self.callbacks.iter_mut() {
+                callback(event);
+            }
+            // Receivers which hung up are no longer interested
+            self.senders.retain(|sender| sender.send(*event).is_ok());
+        }
+    }
+
+    fn diff(&mut self, previous: &Status, current: &Status) -> Vec<Event> {
+        let (old, new) = (&previous.flags, &current.flags);
+        let mut events = Vec::new();
+        if old.channel1 != new.channel1 {
+            events.push(Event::ModeChanged {
+                channel: Channel::One,
+                mode: new.channel1,
+            });
+        }
+        if old.channel2 != new.channel2 {
+            events.push(Event::ModeChanged {
+                channel: Channel::Two,
+                mode: new.channel2,
+            });
+        }
+        if old.output != new.output {
+            events.push(Event::OutputChanged(new.output));
+            if new.output == Switch::Off && !std::mem::take(&mut self.expect_off) {
+                events.push(Event::UnexpectedOutputOff);
+            }
+        }
+        if old.lock != new.lock {
+            events.push(Event::LockChanged(new.lock));
+        }
+        if old.beep != new.beep {
+            events.push(Event::BeepChanged(new.beep));
+        }
+        for &(quantity, threshold) in &self.thresholds {
+            let (before, value) = (quantity.of(previous), quantity.of(current));
+            let direction = if before < threshold && value >= threshold {
+                Direction::Rising
+            } else if before >= threshold && value < threshold {
+                Direction::Falling
+            } else {
+                continue;
+            };
+            events.push(Event::ThresholdCrossed {
+                quantity,
+                threshold,
+                value,
+                direction,
+            });
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Flags;
+
+    fn status(flags: u8, current: f32) -> Status {
+        Status {
+            flags: Flags::from(flags),
+            voltage: 5.0,
+            current,
+            set_voltage: 5.0,
+            set_current: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_first_snapshot_is_baseline() {
+        let mut watcher = Watcher::new();
+        assert_eq!(watcher.update(status(0x41, 0.1)), vec![]);
+    }
+
+    #[test]
+    fn test_mode_and_output_changes() {
+        let mut watcher = Watcher::new();
+        let events = watcher.subscribe();
+        watcher.update(status(0x41, 0.1));
+        assert_eq!(
+            watcher.update(status(0x40, 1.0)),
+            vec![Event::ModeChanged {
+                channel: Channel::One,
+                mode: Mode::Cc
+            }]
+        );
+        assert_eq!(
+            watcher.update(status(0x00, 0.0)),
+            vec![
+                Event::OutputChanged(Switch::Off),
+                Event::UnexpectedOutputOff
+            ]
+        );
+        assert_eq!(events.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn test_expected_output_off() {
+        let mut watcher = Watcher::new();
+        watcher.update(status(0x41, 0.1));
+        watcher.expect_output_off();
+        assert_eq!(
+            watcher.update(status(0x01, 0.0)),
+            vec![Event::OutputChanged(Switch::Off)]
+        );
+    }
+
+    #[test]
+    fn test_threshold_crossing() {
+        let mut watcher = Watcher::new().threshold(Quantity::Current, 0.5);
+        watcher.update(status(0x41, 0.1));
+        assert_eq!(watcher.update(status(0x41, 0.2)), vec![]);
+        assert_eq!(
+            watcher.update(status(0x41, 0.5)),
+            vec![Event::ThresholdCrossed {
+                quantity: Quantity::Current,
+                threshold: 0.5,
+                value: 0.5,
+                direction: Direction::Rising
+            }]
+        );
+        assert_eq!(
+            watcher.update(status(0x41, 0.4)),
+            vec![Event::ThresholdCrossed {
+                quantity: Quantity::Current,
+                threshold: 0.5,
+                value: 0.4,
+                direction: Direction::Falling
+            }]
+        );
+    }
+}
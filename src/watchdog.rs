@@ -0,0 +1,328 @@
+//! Host-side watchdog which switches the output of the power supply off
+//! if the controlling program stops renewing its heartbeat.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ka3005p::{Command, Switch};
+//! use ka3005p::watchdog::Watchdog;
+//!
+//! let dev = ka3005p::find_serial_port().unwrap();
+//! let watchdog = Watchdog::new(dev, Duration::from_secs(5));
+//! watchdog.lock().execute(Command::Power(Switch::On)).unwrap();
+//! loop {
+//!     // Do some work, but renew the heartbeat at least every 5 seconds.
+//!     // If this program hangs, crashes or panics the output will be switched off.
+//!     watchdog.feed();
+//! #   break;
+//! }
+//! ```
+use crate::{Command, Ka3005p, Switch};
+use log::{error, warn};
+use serialport::SerialPort;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, TryLockError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a trip waits for the power supply to become available before using the [`Fallback`],
+/// so a running transfer is usually not interrupted
+const GRACE: Duration = Duration::from_millis(250);
+
+struct State {
+    last_feed: Instant,
+    stopped: bool,
+    tripped: bool,
+}
+
+/// A second handle of the serial port, to switch the output off while the power supply is locked,
+/// e.g. by a client which hangs
+struct Fallback {
+    serial: Mutex<Box<dyn SerialPort>>,
+    request: String,
+}
+
+struct Shared {
+    device: Mutex<Ka3005p>,
+    fallback: Option<Fallback>,
+    state: Mutex<State>,
+    wakeup: Condvar,
+    timeout: Duration,
+}
+
+impl Shared {
+    fn device(&self) -> MutexGuard<'_, Ka3005p> {
+        // A panic while the device was in use must not prevent us from switching it off.
+        self.device.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn feed(&self) {
+        let mut state = self.state();
+        state.last_feed = Instant::now();
+        if state.tripped {
+            state.tripped = false;
+            self.wakeup.notify_all();
+        }
+    }
+
+    fn switch_off(&self) {
+        Self::switch_off_device(&mut self.device());
+    }
+
+    fn switch_off_device(device: &mut Ka3005p) {
+        if let Err(e) = device.execute(Command::Power(Switch::Off)) {
+            error!("Watchdog could not switch off the output: {:#}", e);
+        }
+    }
+
+    /// Switch the output off after the heartbeat lapsed, without waiting for a client which holds the power supply.
+    fn trip(&self) {
+        let deadline = Instant::now() + GRACE;
+        loop {
+            match self.device.try_lock() {
+                Ok(mut device) => return Self::switch_off_device(&mut device),
+                Err(TryLockError::Poisoned(e)) => {
+                    return Self::switch_off_device(&mut e.into_inner())
+                }
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(TryLockError::WouldBlock) => break,
+            }
+        }
+        let Some(fallback) = &self.fallback else {
+            warn!("The power supply is in use and its port can't be cloned, waiting for it");
+            return self.switch_off();
+        };
+        warn!("The power supply is in use, switching the output off via a second handle");
+        let mut serial = fallback
+            .serial
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let result = serial
+            .write_all(fallback.request.as_bytes())
+            .and_then(|_| serial.flush());
+        if let Err(e) = result {
+            error!("Watchdog could not switch off the output: {}", e);
+        }
+    }
+}
+
+/// Guards a power supply and switches its output off once the heartbeat lapses
+/// or the guard is dropped (e.g. while unwinding from a panic).
+///
+/// Note: Destructors do not run if the process is terminated via [`std::process::exit`]
+/// or killed by a signal, the heartbeat timeout still applies if the process hangs.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<thread::JoinHandle<()>>,
+    armed: bool,
+}
+
+impl Watchdog {
+    /// Take control over the power supply and start the watchdog.
+    /// The heartbeat needs to be renewed via [`Watchdog::feed`] at least once per `timeout`.
+    ///
+    /// If the power supply is still in use when the heartbeat lapsed (e.g. a client hangs while holding [`Watchdog::lock`]),
+    /// the output is switched off via a clone of the serial port. Note: The clone isn't updated by reconnects.
+    ///
+    /// Ramps of the setpoints (see [`Ka3005p::set_slew_rate`]) renew the heartbeat between their steps.
+    pub fn new(mut device: Ka3005p, timeout: Duration) -> Self {
+        let fallback = match device.off_switch() {
+            Ok((serial, request)) => Some(Fallback {
+                serial: Mutex::new(serial),
+                request,
+            }),
+            Err(e) => {
+                warn!("Watchdog can't clone the serial port: {:#}", e);
+                None
+            }
+        };
+        let shared = Arc::new_cyclic(|shared: &Weak<Shared>| {
+            let shared = shared.clone();
+            device.set_heartbeat(Some(Box::new(move || {
+                if let Some(shared) = shared.upgrade() {
+                    shared.feed();
+                }
+            })));
+            Shared {
+                device: Mutex::new(device),
+                fallback,
+                state: Mutex::new(State {
+                    last_feed: Instant::now(),
+                    stopped: false,
+                    tripped: false,
+                }),
+                wakeup: Condvar::new(),
+                timeout,
+            }
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || Self::monitor(&shared))
+        };
+        Watchdog {
+            shared,
+            thread: Some(thread),
+            armed: true,
+        }
+    }
+
+    fn monitor(shared: &Shared) {
+        let mut state = shared.state();
+        loop {
+            if state.stopped {
+                return;
+            }
+            if state.tripped {
+                // Nothing to do until the heartbeat is renewed
+                state = shared
+                    .wakeup
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+            let elapsed = state.last_feed.elapsed();
+            if elapsed >= shared.timeout {
+                state.tripped = true;
+                drop(state);
+                warn!(
+                    "Watchdog heartbeat lapsed after {:?}, switching output off",
+                    elapsed
+                );
+                shared.trip();
+                state = shared.state();
+                continue;
+            }
+            state = shared
+                .wakeup
+                .wait_timeout(state, shared.timeout - elapsed)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Renew the heartbeat. This also rearms a tripped watchdog.
+    pub fn feed(&self) {
+        self.shared.feed();
+    }
+
+    /// Check if the watchdog has switched the output off because the heartbeat lapsed.
+    /// The output stays off until it is explicitly enabled again, the next [`Watchdog::feed`] rearms the watchdog.
+    pub fn tripped(&self) -> bool {
+        self.shared.state().tripped
+    }
+
+    /// The time within which the heartbeat needs to be renewed.
+    pub fn timeout(&self) -> Duration {
+        self.shared.timeout
+    }
+
+    /// Get access to the guarded power supply.
+    pub fn lock(&self) -> MutexGuard<'_, Ka3005p> {
+        self.shared.device()
+    }
+
+    /// Stop the watchdog without switching the output off and hand back the power supply.
+    pub fn disarm(mut self) -> Ka3005p {
+        self.armed = false;
+        self.stop();
+        let shared = self.shared.clone();
+        drop(self);
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => {
+                let mut device = shared
+                    .device
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner);
+                device.set_heartbeat(None);
+                device
+            }
+            Err(_) => unreachable!("watchdog thread has been joined"),
+        }
+    }
+
+    fn stop(&mut self) {
+        self.shared.state().stopped = true;
+        self.shared.wakeup.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+        if self.armed {
+            self.shared.switch_off();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPort;
+
+    fn switch_offs(mock: &MockPort) -> usize {
+        mock.requests().iter().filter(|r| *r == "OUT0").count()
+    }
+
+    #[test]
+    fn test_trip_and_rearm() {
+        let mock = MockPort::new();
+        let dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let watchdog = Watchdog::new(dev, Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(100));
+        assert!(watchdog.tripped());
+        assert_eq!(switch_offs(&mock), 1);
+        watchdog.feed();
+        assert!(!watchdog.tripped());
+        thread::sleep(Duration::from_millis(100));
+        assert!(watchdog.tripped());
+        assert_eq!(switch_offs(&mock), 2);
+    }
+
+    #[test]
+    fn test_trip_while_locked() {
+        let mock = MockPort::new();
+        let dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let watchdog = Watchdog::new(dev, Duration::from_millis(20));
+        // A client which hangs while holding the power supply
+        let _device = watchdog.lock();
+        thread::sleep(GRACE + Duration::from_millis(200));
+        assert!(watchdog.tripped());
+        assert_eq!(switch_offs(&mock), 1);
+    }
+
+    #[test]
+    fn test_stop() {
+        let mock = MockPort::new();
+        let dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        let dev = Watchdog::new(dev, Duration::from_secs(60)).disarm();
+        assert_eq!(switch_offs(&mock), 0);
+        drop(Watchdog::new(dev, Duration::from_secs(60)));
+        assert_eq!(mock.requests(), ["OUT0"]);
+    }
+
+    #[test]
+    fn test_ramp_renews_heartbeat() {
+        let mock = MockPort::new();
+        mock.on("STATUS?", crate::mock::Reply::Data(vec![0x51]))
+            .on("VSET1?", crate::mock::Reply::data("5.00"));
+        let mut dev = Ka3005p::new_from_serial(Box::new(mock.clone())).unwrap();
+        dev.set_slew_rate(Some(crate::slew::SlewRate {
+            voltage: Some(1.0),
+            current: None,
+            interval: Duration::from_millis(20),
+        }));
+        let watchdog = Watchdog::new(dev, Duration::from_millis(100));
+        // Takes 400ms while holding the power supply
+        watchdog.lock().execute(Command::Voltage(5.4)).unwrap();
+        assert!(!watchdog.tripped());
+        assert_eq!(switch_offs(&mock), 0);
+    }
+}